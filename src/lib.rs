@@ -0,0 +1,6536 @@
+//! The `cargo contemplate` subcommand, and [`scaffold`] for tools that create
+//! projects from templates without running it.
+
+use flate2::read::GzDecoder;
+use fs_extra::dir::CopyOptions;
+use git2::build::{CheckoutBuilder, RepoBuilder};
+use git2::{Direction, FetchOptions, Oid, Remote, RemoteCallbacks, Repository};
+use globset::{Glob, GlobBuilder, GlobMatcher, GlobSet, GlobSetBuilder};
+use phf::phf_map;
+use rand::{distributions::Alphanumeric, Rng};
+use serde::{Deserialize, Serialize};
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::io::{self, IsTerminal, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use url::Url;
+
+/// Says a line of what a run is doing, or of what went wrong: printed, or
+/// kept for the caller of [`scaffold`] on this thread, see `SAID`.
+macro_rules! say {
+    ($($arg:tt)*) => {
+        say(format!($($arg)*))
+    };
+}
+
+thread_local! {
+    /// The lines a [`scaffold`] under way on this thread collects, rather than
+    /// have them printed.
+    static SAID: RefCell<Option<Vec<String>>> = const { RefCell::new(None) };
+}
+
+fn say(line: String) {
+    SAID.with(|said| match said.borrow_mut().as_mut() {
+        Some(lines) => lines.push(line),
+        None => println!("{}", line),
+    });
+}
+
+/// Shape of the random names given to scratch directories.
+struct RandomName {
+    len: usize,
+    /// Only `a-z0-9`, so names can't collide on case-insensitive filesystems.
+    lowercase: bool,
+}
+
+const RANDOM_NAME: RandomName = RandomName {
+    len: 12,
+    lowercase: true,
+};
+
+fn random_name() -> String {
+    const LOWERCASE: &[u8] = b"abcdefghijklmnopqrstuvwxyz0123456789";
+    let mut rng = rand::thread_rng();
+    (0..RANDOM_NAME.len)
+        .map(|_| {
+            if RANDOM_NAME.lowercase {
+                char::from(LOWERCASE[rng.gen_range(0..LOWERCASE.len())])
+            } else {
+                char::from(rng.sample(Alphanumeric))
+            }
+        })
+        .collect()
+}
+
+fn random_path() -> PathBuf {
+    Path::join(Path::new("/tmp"), random_name())
+}
+
+static URLS: phf::Map<&'static str, (&'static str, &'static str, &'static str)> = phf_map! {
+    "phat-contract-with-sideprog" => ("https://github.com/tenheadedlion/phat-contract-starter.git", "master", "log_server-a00c26e4ff2173713db9afca5a82aee3"),
+    "phat-contract" => ("https://github.com/tenheadedlion/phat-contract-starter.git", "plain-phat-contract", "erc20-497c0f607b393edb86f8da1bf053fb06"),
+};
+
+/// Why a run failed, each kind with an exit code of its own. What exactly went
+/// wrong has been said by the time it is returned, see [`Failure`].
+#[derive(Debug)]
+pub enum Error {
+    NoSuchClass,
+    FileSystemFault,
+    FileSystemRename,
+    FileSystemRemoveDir,
+    GitFault,
+    UndefinedVariable,
+    InvalidDest,
+    InvalidArchive,
+    InvalidManifest,
+    NothingFetched,
+    CannotParseUrl,
+    NoSuchPrefix,
+    LicenseExists,
+    InvalidCargoToml,
+    InsufficientSpace,
+    InvalidRegistry,
+    BadSignature,
+    NoPackage,
+    InvalidCrateName,
+    EmptyTemplate,
+    RegistryExists,
+    InvalidGlob,
+    Cancelled,
+    InvalidRev,
+    NotInRepository,
+    InheritanceCycle,
+    AmbiguousPackage,
+    StrictWarning,
+    InvalidIndex,
+    LockTimeout,
+    InvalidVarsFile,
+    UnusedVariable,
+    PackageOutsideTemplate,
+    NoSuchRelease,
+    DownloadFault,
+    InvalidConfig,
+    CheckFailed,
+    InvalidFileName,
+    FileNameCollision,
+    EmptyPackage,
+    TemplateRender,
+    DestExists,
+    InvalidLock,
+    LockedCommitMissing,
+    LfsPointers,
+    UpdateConflict,
+    NetworkDisabled,
+    NoSuchPathSpec,
+    HostNotAllowed,
+    InvalidFlags,
+}
+
+impl Error {
+    /// The exit status for the error, the same `code` as under `--json`.
+    /// Numbered from 10, clap using 1 and 2 for bad arguments; stable across
+    /// releases, so new variants get new numbers.
+    pub fn code(&self) -> i32 {
+        match self {
+            Error::NoSuchClass => 10,
+            Error::FileSystemFault => 11,
+            Error::FileSystemRename => 12,
+            Error::FileSystemRemoveDir => 13,
+            Error::GitFault => 14,
+            Error::UndefinedVariable => 15,
+            Error::InvalidDest => 16,
+            Error::InvalidArchive => 17,
+            Error::InvalidManifest => 18,
+            Error::NothingFetched => 19,
+            Error::CannotParseUrl => 20,
+            Error::NoSuchPrefix => 21,
+            Error::LicenseExists => 22,
+            Error::InvalidCargoToml => 23,
+            Error::InsufficientSpace => 24,
+            Error::InvalidRegistry => 25,
+            Error::BadSignature => 26,
+            Error::NoPackage => 27,
+            Error::InvalidCrateName => 28,
+            Error::EmptyTemplate => 29,
+            Error::RegistryExists => 30,
+            Error::InvalidGlob => 31,
+            // what a shell reports for Ctrl-C
+            Error::Cancelled => 130,
+            Error::InvalidRev => 32,
+            Error::NotInRepository => 33,
+            Error::InheritanceCycle => 34,
+            Error::AmbiguousPackage => 35,
+            Error::StrictWarning => 36,
+            Error::InvalidIndex => 37,
+            Error::LockTimeout => 38,
+            Error::InvalidVarsFile => 39,
+            Error::UnusedVariable => 40,
+            Error::PackageOutsideTemplate => 41,
+            Error::NoSuchRelease => 42,
+            Error::DownloadFault => 43,
+            Error::InvalidConfig => 44,
+            Error::CheckFailed => 45,
+            Error::InvalidFileName => 46,
+            Error::FileNameCollision => 47,
+            Error::EmptyPackage => 48,
+            Error::TemplateRender => 49,
+            Error::DestExists => 50,
+            Error::InvalidLock => 51,
+            Error::LockedCommitMissing => 52,
+            Error::LfsPointers => 53,
+            Error::UpdateConflict => 54,
+            Error::NetworkDisabled => 55,
+            Error::NoSuchPathSpec => 56,
+            Error::HostNotAllowed => 57,
+            Error::InvalidFlags => 58,
+        }
+    }
+}
+
+/// What went wrong in a few words; the details have been printed by then.
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let message = match self {
+            Error::NoSuchClass => "no such class",
+            Error::FileSystemFault => "a file system operation failed",
+            Error::FileSystemRename => "the project could not be moved into place",
+            Error::FileSystemRemoveDir => "a directory could not be removed",
+            Error::GitFault => "a git operation failed",
+            Error::UndefinedVariable => "the template uses an undefined variable",
+            Error::InvalidDest => "the destination is not valid",
+            Error::InvalidArchive => "the archive is not valid",
+            Error::InvalidManifest => "the template's contemplate.toml is not valid",
+            Error::NothingFetched => "--rev fetched nothing",
+            Error::CannotParseUrl => "a url could not be parsed",
+            Error::NoSuchPrefix => "the --strip-prefix directory doesn't exist",
+            Error::LicenseExists => "the template already has a license",
+            Error::InvalidCargoToml => "the project's Cargo.toml is not valid",
+            Error::InsufficientSpace => "there is not enough free space",
+            Error::InvalidRegistry => "templates.toml is not valid",
+            Error::BadSignature => "the template's signature could not be verified",
+            Error::NoPackage => "the template's package was not found",
+            Error::InvalidCrateName => "the crate name is not valid",
+            Error::EmptyTemplate => "the template has no commits",
+            Error::RegistryExists => "templates.toml exists already",
+            Error::InvalidGlob => "a glob is not valid",
+            Error::Cancelled => "cancelled",
+            Error::InvalidRev => "a rev or branch name is not valid",
+            Error::NotInRepository => "not inside a git repository",
+            Error::InheritanceCycle => "the template's bases form a cycle",
+            Error::AmbiguousPackage => "the package is ambiguous",
+            Error::StrictWarning => "a warning was raised under --strict",
+            Error::InvalidIndex => "the --index file is not valid",
+            Error::LockTimeout => "timed out waiting for the cache lock",
+            Error::InvalidVarsFile => "the --set-from-file file is not valid",
+            Error::UnusedVariable => "a variable from --set-from-file is not used",
+            Error::PackageOutsideTemplate => "the package is outside of the template",
+            Error::NoSuchRelease => "no such release or asset",
+            Error::DownloadFault => "a download failed",
+            Error::InvalidConfig => "config.toml is not valid",
+            Error::CheckFailed => "cargo check failed",
+            Error::InvalidFileName => "a file name is not valid once substituted",
+            Error::FileNameCollision => "two files have the same name once substituted",
+            Error::EmptyPackage => "the template's package has no files",
+            Error::TemplateRender => "a .liquid file could not be rendered",
+            Error::DestExists => "the destination already exists",
+            Error::InvalidLock => "contemplate.lock doesn't pin this template",
+            Error::LockedCommitMissing => {
+                "the commit of contemplate.lock is gone from the template"
+            }
+            Error::LfsPointers => "the template has Git LFS files that could not be fetched",
+            Error::UpdateConflict => "some of the template's changes did not apply",
+            Error::NetworkDisabled => "the network is needed, but --no-network is given",
+            Error::NoSuchPathSpec => "a --checkout-path-spec matches nothing in the template",
+            Error::HostNotAllowed => "the template's host is not in allowed_hosts",
+            Error::InvalidFlags => "the flags are not valid",
+        };
+        f.write_str(message)
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Ends the program on `e`, exiting with its code: as a JSON object on
+/// stderr with `json`, for tools to tell the errors apart, else as a line.
+fn fail(e: Error, json: bool) -> ! {
+    match json {
+        true => eprintln!(
+            "{}",
+            serde_json::json!({
+                "error": {
+                    "code": e.code(),
+                    "kind": format!("{:?}", e),
+                    "message": e.to_string(),
+                }
+            })
+        ),
+        false => eprintln!("error: {}", e),
+    }
+    remove_scratch();
+    std::process::exit(e.code());
+}
+
+/// Prints what went wrong with the file system, for `?` to carry on with.
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        say!("{}", e);
+        Error::FileSystemFault
+    }
+}
+
+/// Prints what git said, for `?` to carry on with.
+impl From<git2::Error> for Error {
+    fn from(e: git2::Error) -> Self {
+        say!("{}", e);
+        Error::GitFault
+    }
+}
+
+#[derive(Debug)]
+struct Args {
+    class: String,
+    dest: PathBuf,
+    vars: HashMap<String, String>,
+    rev: Option<String>,
+    mirror: Option<String>,
+    strip_prefix: Option<String>,
+    branch: Option<String>,
+    license: Option<String>,
+    force: bool,
+    checkout_submodules: bool,
+    min_free_space: Option<u64>,
+    verify_signature: bool,
+    trace_timings: bool,
+    prefix: Option<String>,
+    prefix_dir: bool,
+    max_file_size: u64,
+    verbose: bool,
+    cache: bool,
+    replace_in: Vec<String>,
+    template_ref_info: bool,
+    dest_exists: DestExists,
+    keep_tmp: bool,
+    ref_type: Option<String>,
+    relative_to_repo_root: bool,
+    no_progress: bool,
+    progress_interval: u64,
+    strict: bool,
+    author: Option<String>,
+    email: Option<String>,
+    git_init: bool,
+    dry_run: bool,
+    fetch_all_branches: bool,
+    no_cruft_filter: bool,
+    package: Option<String>,
+    template_subpath: Option<String>,
+    vars_file: Option<String>,
+    commit_message: Option<String>,
+    preserve_timestamps: bool,
+    rename_scope: Vec<String>,
+    branch_from_rev: Option<String>,
+    from_local: bool,
+    index: Option<PathBuf>,
+    exclude_package_metadata: bool,
+    wait_for_lock: Option<u64>,
+    no_rewrite: bool,
+    no_substitute: bool,
+    marker: bool,
+    minimal: bool,
+    minimal_include: Vec<String>,
+    print_plan: bool,
+    json: bool,
+    fmt: bool,
+    check: bool,
+    template_readme: bool,
+    keep_git: Option<bool>,
+    clone_only: Option<PathBuf>,
+    allow_empty: bool,
+    tree: bool,
+    liquid: bool,
+    source_ref_lock: bool,
+    locked: bool,
+    worktree: bool,
+    flatten: bool,
+    checkout_path_spec: Vec<String>,
+    rename_git_remote: Option<String>,
+    clear_git_remote: bool,
+    no_network: bool,
+    proxy: Option<String>,
+}
+
+fn parse_var(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("expected KEY=VALUE, got `{}`", s)),
+    }
+}
+
+/// Reads the flat table of variables in a `--set-from-file` file. Numbers
+/// and booleans are taken as they are written; nested tables and arrays are
+/// not variables.
+fn read_vars(file: &str) -> Result<HashMap<String, String>, Error> {
+    let content = std::fs::read_to_string(file).map_err(|e| {
+        say!("{}: {}", file, e);
+        Error::InvalidVarsFile
+    })?;
+    let table: toml::Table = content.parse().map_err(|e| {
+        say!("{}: {}", file, e);
+        Error::InvalidVarsFile
+    })?;
+    table
+        .into_iter()
+        .map(|(name, value)| match value {
+            toml::Value::String(value) => Ok((name, value)),
+            toml::Value::Table(_) | toml::Value::Array(_) => {
+                say!("{}: `{}` is not a string, number or boolean", file, name);
+                Err(Error::InvalidVarsFile)
+            }
+            value => Ok((name, value.to_string())),
+        })
+        .collect()
+}
+
+/// Whether `text` has a `{{name}}` or `{{ name }}` token.
+fn mentions(text: &str, name: &str) -> bool {
+    text.contains(&format!("{{{{{}}}}}", name)) || text.contains(&format!("{{{{ {} }}}}", name))
+}
+
+/// The variables in `names` that neither the files of `project` nor the
+/// template's manifest refer to.
+fn unused_vars(
+    project: &Path,
+    manifest: &Manifest,
+    names: &[String],
+) -> Result<Vec<String>, Error> {
+    let message = manifest.post_create_message.as_deref().unwrap_or_default();
+    let mut unused: Vec<_> = names
+        .iter()
+        .filter(|name| !manifest.conditional.contains_key(*name) && !mentions(message, name))
+        .cloned()
+        .collect();
+    for path in files(project)? {
+        if unused.is_empty() {
+            break;
+        }
+        // a variable may name a file rather than be in one
+        let relative = path.strip_prefix(project).unwrap().to_string_lossy();
+        unused.retain(|name| !mentions(&relative, name));
+        if let Ok(Ok(content)) = std::fs::read(&path).map(String::from_utf8) {
+            unused.retain(|name| !mentions(&content, name));
+        }
+    }
+    Ok(unused)
+}
+
+/// Replaces every `{{name}}` in `template` with the value of `name` in `vars`.
+fn render(template: &str, vars: &HashMap<String, String>) -> Result<String, Error> {
+    let mut out = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let end = rest[start..].find("}}").ok_or(Error::UndefinedVariable)? + start;
+        let name = rest[start + 2..end].trim();
+        let value = vars.get(name).ok_or_else(|| {
+            say!("undefined variable `{}`", name);
+            Error::UndefinedVariable
+        })?;
+        out.push_str(&rest[..start]);
+        out.push_str(value);
+        rest = &rest[end + 2..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// Resolves the placeholders in DEST, making sure the substituted values
+/// did not smuggle in extra path components.
+fn resolve_dest(dest: &str, vars: &HashMap<String, String>) -> Result<String, Error> {
+    let resolved = render(dest, vars)?;
+    let legal = !resolved.contains('\0')
+        && Path::new(&resolved).components().count() == Path::new(dest).components().count()
+        && matches!(
+            Path::new(&resolved).components().next_back(),
+            Some(Component::Normal(_) | Component::CurDir)
+        );
+    if !legal {
+        say!("`{}` is not a valid destination", resolved);
+        return Err(Error::InvalidDest);
+    }
+    Ok(resolved)
+}
+
+/// `path` with a leading `~` and its `$VAR` and `${VAR}` references expanded
+/// the way a shell would have, for paths passed on by scripts unexpanded.
+fn expand(path: &str) -> Result<String, Error> {
+    match shellexpand::full(path) {
+        Ok(expanded) => Ok(expanded.into_owned()),
+        Err(e) => {
+            say!("cannot expand `{}`: {}", path, e);
+            Err(Error::InvalidDest)
+        }
+    }
+}
+
+/// `path` made absolute against `base`, with its `.` and `..` resolved
+/// without looking at the file system, where it may not exist yet.
+fn absolute(base: &Path, path: &Path) -> PathBuf {
+    let mut absolute = PathBuf::new();
+    for component in base.join(path).components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                absolute.pop();
+            }
+            component => absolute.push(component),
+        }
+    }
+    absolute
+}
+
+#[derive(Debug)]
+enum Source {
+    Git {
+        url: String,
+        branch: String,
+        /// `None` when the template's manifest names its package.
+        package: Option<String>,
+    },
+    /// A local `.tar`, `.tar.gz` or `.zip` file holding a single package directory.
+    Archive(PathBuf),
+    /// An archive attached to a GitHub release, downloaded before it is extracted.
+    Release(Release),
+    /// A template laid out in a local directory, used as it is without git.
+    Local {
+        dir: PathBuf,
+        /// `None` for the template's manifest to name, or the whole directory.
+        package: Option<String>,
+    },
+}
+
+/// Points `url` at the same repository path under `mirror`, e.g.
+/// `https://github.com/org/repo.git` becomes `https://git.internal/github/org/repo.git`
+/// for a mirror of `https://git.internal/github`, and so does the scp-like
+/// `git@github.com:org/repo.git`. A template on this machine is left alone.
+fn mirrored(url: &str, mirror: &str, strict: bool) -> Result<String, Error> {
+    let parse = |url: &str| {
+        Url::parse(url)
+            .ok()
+            .filter(|parsed| parsed.host().is_some())
+            .ok_or_else(|| {
+                say!("`{}` is not a valid url with a host", url);
+                Error::CannotParseUrl
+            })
+    };
+    // a template on this machine has nothing to mirror
+    if url.starts_with("file://") || Path::new(url).exists() {
+        return Ok(url.to_string());
+    }
+    // `git@github.com:org/repo.git` isn't a url, its path is what follows the `:`
+    let scp = match url.split_once(':') {
+        Some((host, path)) if !host.contains('/') && !path.starts_with("//") => Some(path),
+        _ => None,
+    };
+    let (https, path) = match scp {
+        Some(path) => (false, format!("/{}", path.trim_start_matches('/'))),
+        None => {
+            let original = parse(url)?;
+            (original.scheme() == "https", original.path().to_string())
+        }
+    };
+    let rewritten = parse(&format!("{}{}", mirror.trim_end_matches('/'), path))?;
+    if https && rewritten.scheme() == "http" {
+        warn(
+            strict,
+            &format!(
+                "the mirror uses plain http, {} will be fetched WITHOUT TLS as {}",
+                url, rewritten
+            ),
+        )?;
+    }
+    Ok(rewritten.to_string())
+}
+
+/// Prints `message` as a warning, which `--strict` makes an error.
+fn warn(strict: bool, message: &str) -> Result<(), Error> {
+    say!("warning: {}", message);
+    match strict {
+        true => Err(Error::StrictWarning),
+        false => Ok(()),
+    }
+}
+
+/// `RepoBuilder::branch` wants a plain branch name, so drop the prefixes people
+/// carry over from `git checkout origin/foo`. A fresh clone only has the
+/// `origin` remote, so that is the only remote name stripped.
+fn local_branch(branch: &str) -> &str {
+    [
+        "refs/heads/",
+        "refs/remotes/origin/",
+        "remotes/origin/",
+        "origin/",
+    ]
+    .iter()
+    .find_map(|prefix| branch.strip_prefix(prefix))
+    .unwrap_or(branch)
+}
+
+/// What `--dest-exists` does with a destination that is there already.
+#[derive(Debug, Clone, Copy)]
+enum DestExists {
+    /// Refuse to go on, before anything is fetched.
+    Error,
+    /// Copy the template's files in one by one, keeping the ones it has.
+    Merge,
+    /// Like `Merge`, replacing the files it has.
+    Overwrite,
+    /// Ask which of the others, on a terminal.
+    Prompt,
+}
+
+/// Whether the template goes into the destination file by file, `Some` of
+/// whether the files there are replaced, or as a fresh directory renamed
+/// into place. An empty directory counts as not being there, unless it holds
+/// the staging directory, as the current directory does with a `.` DEST.
+fn existing_dest(ctx: &Context) -> Result<Option<bool>, Error> {
+    let exists = match std::fs::read_dir(&ctx.path) {
+        Ok(mut entries) => entries.next().is_some(),
+        Err(_) => ctx.path.symlink_metadata().is_ok(),
+    };
+    if !exists {
+        let staged_inside = ctx.path.is_dir() && ctx.staging_path.starts_with(&ctx.path);
+        return Ok(staged_inside.then_some(false));
+    }
+    if !ctx.path.is_dir() {
+        say!("{} exists and is not a directory", ctx.path.display());
+        return Err(Error::DestExists);
+    }
+    match ctx.dest_exists {
+        DestExists::Merge => Ok(Some(false)),
+        DestExists::Overwrite => Ok(Some(true)),
+        DestExists::Error => {
+            say!(
+                "{} already exists, pass --dest-exists merge to add to it",
+                ctx.path.display()
+            );
+            Err(Error::DestExists)
+        }
+        DestExists::Prompt if !io::stdin().is_terminal() => {
+            say!(
+                "{} already exists, and there is no terminal to ask what to do",
+                ctx.path.display()
+            );
+            Err(Error::DestExists)
+        }
+        DestExists::Prompt => {
+            print!(
+                "{} already exists: [m]erge, [o]verwrite or [a]bort? ",
+                ctx.path.display()
+            );
+            io::stdout().flush()?;
+            let mut answer = String::new();
+            io::stdin().read_line(&mut answer)?;
+            match answer.trim() {
+                "m" | "merge" => Ok(Some(false)),
+                "o" | "overwrite" => Ok(Some(true)),
+                _ => Err(Error::Cancelled),
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+enum ArchiveKind {
+    Tar,
+    TarGz,
+    Zip,
+}
+
+/// Sniffs the archive type from the magic bytes, so misnamed files still work.
+fn archive_kind(path: &Path) -> Result<ArchiveKind, Error> {
+    let mut header = [0u8; 262];
+    let len = std::fs::File::open(path).and_then(|mut f| f.read(&mut header))?;
+    match &header[..len] {
+        [0x1f, 0x8b, ..] => Ok(ArchiveKind::TarGz),
+        [b'P', b'K', 0x03, 0x04, ..] => Ok(ArchiveKind::Zip),
+        h if h.len() >= 262 && &h[257..262] == b"ustar" => Ok(ArchiveKind::Tar),
+        _ => Err(Error::InvalidArchive),
+    }
+}
+
+/// A `gh-release:owner/repo@tag/asset` class.
+#[derive(Debug)]
+struct Release {
+    owner: String,
+    repo: String,
+    tag: String,
+    asset: String,
+}
+
+const RELEASE_PREFIX: &str = "gh-release:";
+
+/// Parses a `gh-release:` class; tags may have slashes, asset names can't.
+fn parse_release(class: &str) -> Result<Release, Error> {
+    let parsed = class.strip_prefix(RELEASE_PREFIX).and_then(|rest| {
+        let (repo, rest) = rest.split_once('@')?;
+        let (owner, repo) = repo.split_once('/')?;
+        let (tag, asset) = rest.rsplit_once('/')?;
+        [owner, repo, tag, asset]
+            .iter()
+            .all(|part| !part.is_empty())
+            .then(|| Release {
+                owner: owner.to_string(),
+                repo: repo.to_string(),
+                tag: tag.to_string(),
+                asset: asset.to_string(),
+            })
+    });
+    parsed.ok_or_else(|| {
+        say!(
+            "`{}` is not of the form {}owner/repo@tag/asset",
+            class,
+            RELEASE_PREFIX
+        );
+        Error::NoSuchClass
+    })
+}
+
+/// Downloads the asset of `release` to `to` through the GitHub API, at
+/// `GITHUB_API_URL` for GitHub Enterprise. `GITHUB_TOKEN`, when set, is sent
+/// along for private repositories and the higher rate limit.
+fn download_release(release: &Release, to: &Path, network: &Network) -> Result<(), Error> {
+    #[derive(Deserialize)]
+    struct GithubRelease {
+        assets: Vec<GithubAsset>,
+    }
+    #[derive(Deserialize)]
+    struct GithubAsset {
+        name: String,
+        /// The API's URL for the asset, which unlike `browser_download_url`
+        /// works for private repositories too.
+        url: String,
+    }
+    let api = env::var("GITHUB_API_URL").unwrap_or_else(|_| "https://api.github.com".to_string());
+    allow_host(&api)?;
+    allow_network(&api, network)?;
+    let token = env::var("GITHUB_TOKEN")
+        .ok()
+        .filter(|token| !token.is_empty());
+    let agent = match network
+        .proxy
+        .as_deref()
+        .and_then(|proxy| ureq::Proxy::new(proxy).ok())
+    {
+        Some(proxy) => ureq::AgentBuilder::new().proxy(proxy).build(),
+        None => ureq::agent(),
+    };
+    let get = |url: &str, accept: &str| {
+        let mut request = agent.get(url).set("Accept", accept).set(
+            "User-Agent",
+            concat!("cargo-contemplate/", env!("CARGO_PKG_VERSION")),
+        );
+        if let Some(token) = &token {
+            request = request.set("Authorization", &format!("Bearer {}", token));
+        }
+        request.call().map_err(|e| match e {
+            ureq::Error::Status(404, _) => {
+                say!(
+                    "{}/{} has no release {}, or it is private and GITHUB_TOKEN isn't set",
+                    release.owner,
+                    release.repo,
+                    release.tag
+                );
+                Error::NoSuchRelease
+            }
+            ureq::Error::Status(403 | 429, _) if token.is_none() => {
+                say!("{}, setting GITHUB_TOKEN raises the rate limit", e);
+                Error::DownloadFault
+            }
+            e => {
+                say!("{}", e);
+                Error::DownloadFault
+            }
+        })
+    };
+
+    let url = format!(
+        "{}/repos/{}/{}/releases/tags/{}",
+        api.trim_end_matches('/'),
+        release.owner,
+        release.repo,
+        release.tag
+    );
+    let found: GithubRelease = serde_json::from_reader(
+        get(&url, "application/vnd.github+json")?.into_reader(),
+    )
+    .map_err(|e| {
+        say!("{}: {}", url, e);
+        Error::DownloadFault
+    })?;
+    let Some(asset) = found.assets.iter().find(|a| a.name == release.asset) else {
+        let names: Vec<_> = found.assets.iter().map(|a| a.name.as_str()).collect();
+        say!(
+            "release {} of {}/{} has no asset {}, only: {}",
+            release.tag,
+            release.owner,
+            release.repo,
+            release.asset,
+            names.join(", ")
+        );
+        return Err(Error::NoSuchRelease);
+    };
+    say!("{} -> {}", asset.url, to.display());
+    let mut body = get(&asset.url, "application/octet-stream")?.into_reader();
+    let mut file = std::fs::File::create(to)?;
+    io::copy(&mut body, &mut file).map_err(|e| {
+        say!("{}", e);
+        Error::DownloadFault
+    })?;
+    Ok(())
+}
+
+fn lookup(class: &str) -> Result<Source, Error> {
+    match templates()?.into_iter().find(|t| t.name == class) {
+        Some(template) => Ok(Source::Git {
+            url: template.url,
+            branch: template.branch,
+            package: template.package,
+        }),
+        None if class.starts_with(RELEASE_PREFIX) => Ok(Source::Release(parse_release(class)?)),
+        None if Path::new(class).is_file() => Ok(Source::Archive(PathBuf::from(class))),
+        None => Err(Error::NoSuchClass),
+    }
+}
+
+/// Looks `class` up in the `--index` file, when there is one.
+fn lookup_in(class: &str, index: Option<&Path>) -> Result<Source, Error> {
+    match index {
+        Some(index) => read_index(index)?.remove(class).ok_or_else(|| {
+            say!("{} has no `{}`", index.display(), class);
+            Error::NoSuchClass
+        }),
+        None => lookup(class),
+    }
+}
+
+/// An entry of an `--index` file, a template already on disk.
+#[derive(Debug, Deserialize)]
+struct IndexEntry {
+    /// Relative to the index file.
+    path: PathBuf,
+    #[serde(default)]
+    package: Option<String>,
+}
+
+/// The `[templates.<class>]` entries of an `--index` file, every one of
+/// which has to point at a directory.
+fn read_index(index: &Path) -> Result<HashMap<String, Source>, Error> {
+    #[derive(Deserialize)]
+    struct Index {
+        #[serde(default)]
+        templates: BTreeMap<String, IndexEntry>,
+    }
+    let content = std::fs::read_to_string(index).map_err(|e| {
+        say!("{}: {}", index.display(), e);
+        Error::InvalidIndex
+    })?;
+    let entries = toml::from_str::<Index>(&content)
+        .map_err(|e| {
+            say!("{}: {}", index.display(), e);
+            Error::InvalidIndex
+        })?
+        .templates;
+    let base = index.parent().unwrap_or(Path::new("."));
+    let missing: Vec<_> = entries
+        .iter()
+        .filter(|(_, entry)| !base.join(&entry.path).is_dir())
+        .map(|(class, entry)| format!("{} ({})", class, base.join(&entry.path).display()))
+        .collect();
+    if !missing.is_empty() {
+        say!(
+            "{}: these templates are not directories: {}",
+            index.display(),
+            missing.join(", ")
+        );
+        return Err(Error::InvalidIndex);
+    }
+    Ok(entries
+        .into_iter()
+        .map(|(class, entry)| {
+            let source = Source::Local {
+                dir: base.join(entry.path),
+                package: entry.package,
+            };
+            (class, source)
+        })
+        .collect())
+}
+
+/// Prints the branches of CLASS's repository, and with `tags` its tags, as
+/// the remote advertises them, without cloning it.
+fn list_branches(class: &str, tags: bool, format: &str, network: &Network) -> Result<(), Error> {
+    let Source::Git { url, .. } = lookup(class)? else {
+        say!("`{}` is not a git template, it has no branches", class);
+        return Err(Error::NoSuchClass);
+    };
+    allow_host(&url)?;
+    allow_network(&url, network)?;
+    let mut cb = RemoteCallbacks::new();
+    credentials(&mut cb);
+    let mut remote = Remote::create_detached(url.as_str())?;
+    let mut connection = remote
+        .connect_auth(Direction::Fetch, Some(cb), Some(proxy_options(network)))
+        .map_err(|e| fetch_fault(&url, e, network))?;
+    let default = connection
+        .remote()
+        .default_branch()
+        .ok()
+        .and_then(|name| name.as_str().map(String::from));
+    // (name, commit, default)
+    let mut branches = Vec::new();
+    let mut found_tags = Vec::new();
+    for head in connection.list()? {
+        let commit = head.oid().to_string();
+        if let Some(branch) = head.name().strip_prefix("refs/heads/") {
+            let is_default = default.as_deref() == Some(head.name());
+            branches.push((branch.to_string(), commit, is_default));
+        } else if let Some(tag) = head.name().strip_prefix("refs/tags/") {
+            // annotated tags come twice, the second time peeled to their commit
+            if tags && !tag.ends_with("^{}") {
+                found_tags.push((tag.to_string(), commit));
+            }
+        }
+    }
+    match format {
+        "json" => {
+            let branches: Vec<_> = branches
+                .iter()
+                .map(|(name, commit, default)| {
+                    serde_json::json!({ "name": name, "commit": commit, "default": default })
+                })
+                .collect();
+            let mut json = serde_json::json!({ "url": url, "branches": branches });
+            if tags {
+                let tags: Vec<_> = found_tags
+                    .iter()
+                    .map(|(name, commit)| serde_json::json!({ "name": name, "commit": commit }))
+                    .collect();
+                json["tags"] = tags.into();
+            }
+            say!("{}", serde_json::to_string_pretty(&json).unwrap());
+        }
+        _ => {
+            for (name, _, default) in &branches {
+                say!("{} {}", if *default { "*" } else { " " }, name);
+            }
+            for (name, _) in &found_tags {
+                say!("  tags/{}", name);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Prints what CLASS resolves to, as it would be fetched, as `key=value` lines
+/// for use from scripts.
+fn print_url(args: &Args) -> Result<(), Error> {
+    match fetched_source(requested_source(args)?, args)? {
+        Source::Git {
+            url,
+            branch,
+            package,
+        } => {
+            say!("url={}", url);
+            say!("branch={}", branch);
+            say!("package={}", package.unwrap_or_default());
+        }
+        Source::Archive(archive) => say!("archive={}", archive.display()),
+        Source::Release(release) => {
+            say!("repo={}/{}", release.owner, release.repo);
+            say!("tag={}", release.tag);
+            say!("asset={}", release.asset);
+        }
+        Source::Local { dir, package } => {
+            say!("dir={}", dir.display());
+            say!("package={}", package.unwrap_or_default());
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Template {
+    /// The key of the entry in `templates.toml`.
+    #[serde(default, skip_deserializing)]
+    name: String,
+    url: String,
+    branch: String,
+    /// May be left out when the template's `contemplate.toml` names it.
+    #[serde(default)]
+    package: Option<String>,
+    /// What `list --tag` finds the class by, e.g. `["ink", "contract"]`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+}
+
+/// `$CONTEMPLATE_HOME`, or `cargo-contemplate` in the platform's config directory.
+fn config_dir() -> Option<PathBuf> {
+    env::var_os("CONTEMPLATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| dirs::config_dir().map(|dir| dir.join("cargo-contemplate")))
+}
+
+const REGISTRY: &str = "templates.toml";
+
+/// User-defined classes, one `[templates.<class>]` table each, and settings.
+#[derive(Debug, Default, Deserialize)]
+struct Registry {
+    #[serde(default)]
+    templates: BTreeMap<String, Template>,
+    /// Keep a local log of what was scaffolded, for `cargo contemplate history`.
+    #[serde(default)]
+    history: bool,
+}
+
+fn registry_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join(REGISTRY))
+}
+
+fn read_registry() -> Result<Registry, Error> {
+    let path = match registry_path() {
+        Some(path) => path,
+        None => return Ok(Registry::default()),
+    };
+    let content = match std::fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Registry::default()),
+        Err(e) => {
+            say!("{}", e);
+            return Err(Error::FileSystemFault);
+        }
+    };
+    toml::from_str(&content).map_err(|e| {
+        say!("{}: {}", path.display(), e);
+        Error::InvalidRegistry
+    })
+}
+
+const CONFIG: &str = "config.toml";
+
+/// The lockfile `--source-ref-lock` writes into the project and `--locked`
+/// reads from the current directory.
+const LOCKFILE: &str = "contemplate.lock";
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct Lockfile {
+    template: Lock,
+}
+
+/// The exact template a project was made from.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct Lock {
+    /// The class, as given on the command line.
+    name: String,
+    url: String,
+    commit: String,
+}
+
+fn read_lock(path: &Path) -> Result<Lock, Error> {
+    let content = std::fs::read_to_string(path).map_err(|e| {
+        say!("cannot read {}: {}", path.display(), e);
+        Error::InvalidLock
+    })?;
+    let lockfile: Lockfile = toml::from_str(&content).map_err(|e| {
+        say!("{}: {}", path.display(), e);
+        Error::InvalidLock
+    })?;
+    rev_candidates(&lockfile.template.commit, Some("commit"))?;
+    Ok(lockfile.template)
+}
+
+fn write_lock(project: &Path, lock: Lock) -> Result<(), Error> {
+    let content = toml::to_string(&Lockfile { template: lock }).unwrap();
+    std::fs::write(
+        project.join(LOCKFILE),
+        format!(
+            "# written by cargo contemplate --source-ref-lock, read by --locked\n{}",
+            content
+        ),
+    )?;
+    Ok(())
+}
+
+/// The user's preferences, kept in `config.toml` apart from the classes of
+/// the registry.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    #[serde(default)]
+    defaults: Defaults,
+    /// The only hosts templates may be fetched from, when set.
+    allowed_hosts: Option<Vec<String>>,
+}
+
+/// Fails when `config.toml` has `allowed_hosts` and the host of `url` isn't
+/// one of them, or can't be told. A template on this machine has no host to
+/// check.
+fn allow_host(url: &str) -> Result<(), Error> {
+    let Some(allowed) = read_config()?.allowed_hosts else {
+        return Ok(());
+    };
+    if url.starts_with("file://") || Path::new(url).exists() {
+        return Ok(());
+    }
+    let host = Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_ascii_lowercase));
+    let Some(host) = host else {
+        say!(
+            "the host of `{}` can't be told, which allowed_hosts needs",
+            url
+        );
+        return Err(Error::HostNotAllowed);
+    };
+    if !allowed
+        .iter()
+        .any(|allowed| allowed.eq_ignore_ascii_case(&host))
+    {
+        say!(
+            "{} is not one of the allowed_hosts of {}: {}",
+            host,
+            CONFIG,
+            allowed.join(", ")
+        );
+        return Err(Error::HostNotAllowed);
+    }
+    Ok(())
+}
+
+/// What flags default to when neither they nor their environment variables
+/// are given.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Defaults {
+    git_init: Option<bool>,
+    /// Used only when the flags it conflicts with aren't given, see `main`.
+    keep_git: Option<bool>,
+    /// The branch of the `--git-init` commit, as with `--branch-from-rev`.
+    default_branch: Option<String>,
+    author: Option<String>,
+    email: Option<String>,
+    license: Option<String>,
+    mirror: Option<String>,
+}
+
+fn read_config() -> Result<Config, Error> {
+    let Some(path) = config_dir().map(|dir| dir.join(CONFIG)) else {
+        return Ok(Config::default());
+    };
+    let content = match std::fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Config::default()),
+        Err(e) => {
+            say!("{}", e);
+            return Err(Error::FileSystemFault);
+        }
+    };
+    let config: Config = toml::from_str(&content).map_err(|e| {
+        say!("{}: {}", path.display(), e);
+        Error::InvalidConfig
+    })?;
+    if let Some(license) = &config.defaults.license {
+        if !LICENSES.contains(&license.as_str()) {
+            say!(
+                "{}: `{}` is not one of {}",
+                path.display(),
+                license,
+                LICENSES.join(", ")
+            );
+            return Err(Error::InvalidConfig);
+        }
+    }
+    Ok(config)
+}
+
+/// The built-in classes followed by the user's, which win on a name clash.
+fn templates() -> Result<Vec<Template>, Error> {
+    let mut templates: BTreeMap<_, _> = URLS
+        .entries()
+        .map(|(name, (url, branch, package))| {
+            let template = Template {
+                name: name.to_string(),
+                url: url.to_string(),
+                branch: branch.to_string(),
+                package: Some(package.to_string()),
+                tags: Vec::new(),
+            };
+            (name.to_string(), template)
+        })
+        .collect();
+    for (name, mut template) in read_registry()?.templates {
+        template.name = name.clone();
+        templates.insert(name, template);
+    }
+    Ok(templates.into_values().collect())
+}
+
+/// Writes a `templates.toml` listing the built-in classes as commented-out
+/// entries, ready to be copied and edited.
+fn init(force: bool) -> Result<(), Error> {
+    let dir = config_dir().ok_or_else(|| {
+        say!("there is no config directory, set CONTEMPLATE_HOME");
+        Error::FileSystemFault
+    })?;
+    let path = dir.join(REGISTRY);
+    if path.exists() && !force {
+        say!(
+            "{} already exists, pass --force to overwrite it",
+            path.display()
+        );
+        return Err(Error::RegistryExists);
+    }
+
+    let mut content = String::from(
+        "# Classes for `cargo contemplate`, one [templates.<class>] table each.\n\
+         # An entry named after a built-in class replaces it; the built-ins are:\n",
+    );
+    let mut builtins: Vec<_> = URLS.entries().collect();
+    builtins.sort_by_key(|(name, _)| **name);
+    for (name, (url, branch, package)) in builtins {
+        content += &format!(
+            "\n# [templates.{}]\n# url = {}\n# branch = {}\n# package = {}\n",
+            name,
+            toml::Value::from(*url),
+            toml::Value::from(*branch),
+            toml::Value::from(*package),
+        );
+    }
+    std::fs::create_dir_all(&dir).and_then(|_| std::fs::write(&path, content))?;
+    say!("wrote {}", path.display());
+    Ok(())
+}
+
+const HISTORY: &str = "history.log";
+
+/// Appends a `<unix seconds>\t<class>\t<path>` line to the history log in the
+/// config directory, if the user asked for one with `history = true`.
+fn record_history(class: &str, path: &Path) -> Result<(), Error> {
+    let dir = match config_dir() {
+        Some(dir) if read_registry()?.history => dir,
+        _ => return Ok(()),
+    };
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    std::fs::create_dir_all(&dir)
+        .and_then(|_| {
+            std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(dir.join(HISTORY))
+        })
+        .and_then(|mut log| writeln!(log, "{}\t{}\t{}", seconds, class, path.display()))?;
+    Ok(())
+}
+
+/// Prints the history log, oldest first.
+fn history() -> Result<(), Error> {
+    let path = config_dir().map(|dir| dir.join(HISTORY));
+    let content = match path.as_ref().map(std::fs::read_to_string) {
+        Some(Ok(content)) => content,
+        Some(Err(e)) if e.kind() != io::ErrorKind::NotFound => {
+            say!("{}", e);
+            return Err(Error::FileSystemFault);
+        }
+        _ => {
+            say!(
+                "nothing recorded, set `history = true` in {} to start",
+                REGISTRY
+            );
+            return Ok(());
+        }
+    };
+    for line in content.lines() {
+        let mut fields = line.splitn(3, '\t');
+        if let (Some(seconds), Some(class), Some(path)) =
+            (fields.next(), fields.next(), fields.next())
+        {
+            let date = seconds
+                .parse()
+                .map_or_else(|_| seconds.to_string(), utc_date);
+            say!("{}  {}  {}", date, class, path);
+        }
+    }
+    Ok(())
+}
+
+/// `seconds` since the epoch as a `YYYY-MM-DD HH:MM` UTC date, after Howard
+/// Hinnant's `civil_from_days`.
+fn utc_date(seconds: u64) -> String {
+    let (days, rem) = ((seconds / 86400) as i64, seconds % 86400);
+    let z = days + 719468;
+    let (era, doe) = (z.div_euclid(146097), z.rem_euclid(146097));
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60
+    )
+}
+
+/// Prints the known classes as an aligned `table`, a `json` array or `plain`
+/// names, one per line. With `tags`, only the classes with one of them are,
+/// or with all of them when `all` is set; tags are matched ignoring case.
+fn list(format: &str, tags: &[String], all: bool) -> Result<(), Error> {
+    let has_tag = |template: &Template, tag: &String| {
+        template.tags.iter().any(|t| t.eq_ignore_ascii_case(tag))
+    };
+    let templates: Vec<_> = templates()?
+        .into_iter()
+        .filter(|t| match all {
+            _ if tags.is_empty() => true,
+            true => tags.iter().all(|tag| has_tag(t, tag)),
+            false => tags.iter().any(|tag| has_tag(t, tag)),
+        })
+        .collect();
+    match format {
+        "json" => say!("{}", serde_json::to_string_pretty(&templates).unwrap()),
+        "plain" => templates.iter().for_each(|t| say!("{}", t.name)),
+        _ => {
+            let tags: Vec<_> = templates.iter().map(|t| t.tags.join(",")).collect();
+            let rows: Vec<[&str; 5]> =
+                std::iter::once(["NAME", "URL", "BRANCH", "PACKAGE", "TAGS"])
+                    .chain(templates.iter().zip(&tags).map(|(t, tags)| {
+                        [
+                            &*t.name,
+                            &*t.url,
+                            &*t.branch,
+                            t.package.as_deref().unwrap_or("-"),
+                            if tags.is_empty() { "-" } else { tags },
+                        ]
+                    }))
+                    .collect();
+            print_table(&rows);
+        }
+    }
+    Ok(())
+}
+
+/// Prints `rows` in columns as wide as their widest cell.
+fn print_table<const N: usize>(rows: &[[&str; N]]) {
+    let mut widths = [0; N];
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+    for row in rows {
+        let line: Vec<_> = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect();
+        say!("{}", line.join("  ").trim_end());
+    }
+}
+
+/// `update --since-commit`: applies to `project` what its template changed
+/// between the commit of its `contemplate.lock` and the latest one of its
+/// branch, as a patch of the package's files. The changes that don't apply
+/// cleanly are printed instead, and the lock only moves to the latest commit
+/// once all of them did. `{{project_name}}` is substituted in the patch, the
+/// other variables aren't known anymore.
+fn update(project: &Path, network: &Network) -> Result<(), Error> {
+    let lock = read_lock(&project.join(LOCKFILE))?;
+    let Source::Git {
+        branch, package, ..
+    } = lookup(&lock.name)?
+    else {
+        say!("`{}` is not a git template anymore", lock.name);
+        return Err(Error::InvalidLock);
+    };
+    let Ok(workdir) = Repository::open(project) else {
+        say!(
+            "{} needs to be a git repository to be updated",
+            project.display()
+        );
+        return Err(Error::NotInRepository);
+    };
+    let scratch = Scratch::default();
+    let into = random_path();
+    scratch.add(into.clone());
+    clone(
+        &lock.url,
+        &branch,
+        None,
+        None,
+        &into,
+        &mut |_| {},
+        false,
+        &[],
+        network,
+    )?;
+    let template = Repository::open(&into)?;
+    let old = Oid::from_str(&lock.commit).and_then(|oid| template.find_commit(oid));
+    let Ok(old) = old else {
+        say!("{} of {} no longer has {}", branch, lock.url, lock.commit);
+        return Err(Error::LockedCommitMissing);
+    };
+    let new = template.head()?.peel_to_commit()?;
+    if old.id() == new.id() {
+        say!("{} is up to date with {}", project.display(), lock.name);
+        return Ok(());
+    }
+    let package = match package {
+        Some(package) => package,
+        None => read_manifest(&into)?.package.unwrap_or_default(),
+    };
+    let package_tree = |commit: &git2::Commit| -> Result<git2::Tree, Error> {
+        let tree = commit.tree()?;
+        let id = match package.as_str() {
+            "" | "." => tree.id(),
+            package => tree.get_path(Path::new(package))?.id(),
+        };
+        Ok(template.find_tree(id)?)
+    };
+    let diff =
+        template.diff_tree_to_tree(Some(&package_tree(&old)?), Some(&package_tree(&new)?), None)?;
+    let mut patch = Vec::new();
+    diff.print(git2::DiffFormat::Patch, |_, _, line| {
+        if matches!(line.origin(), '+' | '-' | ' ') {
+            patch.push(line.origin() as u8);
+        }
+        patch.extend_from_slice(line.content());
+        true
+    })?;
+    let name = read_package_name(project).unwrap_or_else(|| lock.name.clone());
+    let vars = HashMap::from([("project_name".to_string(), name)]);
+    let patch = fill(&String::from_utf8_lossy(&patch), &vars);
+    let diff = git2::Diff::from_buffer(patch.as_bytes())?;
+
+    // each change is tried on its own, so that one conflict doesn't hold
+    // back the others
+    let apply = |wanted: &[usize], check: bool| {
+        // the callback points back at the options, which can't move after it is set
+        let mut options = git2::ApplyOptions::new();
+        options.check(check);
+        let mut index = 0;
+        options.delta_callback(move |_| {
+            index += 1;
+            wanted.contains(&(index - 1))
+        });
+        workdir.apply(&diff, git2::ApplyLocation::WorkDir, Some(&mut options))
+    };
+    let (clean, conflicting): (Vec<_>, Vec<_>) =
+        (0..diff.deltas().len()).partition(|&i| apply(&[i], true).is_ok());
+    if !clean.is_empty() {
+        apply(&clean, false)?;
+    }
+    let path_of = |i: usize| {
+        let delta = diff.get_delta(i).unwrap();
+        let file = delta.new_file().path().or(delta.old_file().path());
+        file.unwrap_or(Path::new("")).display().to_string()
+    };
+    for &i in &clean {
+        say!("updated {}", path_of(i));
+    }
+    if !conflicting.is_empty() {
+        for &i in &conflicting {
+            say!(
+                "{} does not apply cleanly, the template changed it this way:",
+                path_of(i)
+            );
+            if let Some(mut patch) = git2::Patch::from_diff(&diff, i)? {
+                print!("{}", String::from_utf8_lossy(&patch.to_buf()?));
+            }
+        }
+        say!(
+            "{} still points at {}, for the changes above to be made by hand",
+            LOCKFILE,
+            &lock.commit[..7]
+        );
+        return Err(Error::UpdateConflict);
+    }
+    let commit = new.id().to_string();
+    say!(
+        "updated {} to {}@{}",
+        project.display(),
+        lock.name,
+        &commit[..7]
+    );
+    write_lock(project, Lock { commit, ..lock })
+}
+
+/// The `name` of the package in the Cargo.toml of `project`.
+fn read_package_name(project: &Path) -> Option<String> {
+    let manifest = std::fs::read_to_string(project.join("Cargo.toml")).ok()?;
+    let manifest: toml::Table = toml::from_str(&manifest).ok()?;
+    Some(manifest.get("package")?.get("name")?.as_str()?.to_string())
+}
+
+/// `verify`: fetches CLASS and checks that it has a package with a valid
+/// Cargo.toml, and that its `contemplate.toml` is valid, down to the classes
+/// it names, without scaffolding anything. Stops at the first problem unless
+/// `all` wants every one of them reported; the first is what it fails with.
+fn verify(class: &str, all: bool, network: &Network) -> Result<(), Error> {
+    let mut problems = Vec::new();
+    let mut report = |result: Result<(), Error>| match result {
+        Err(e) if all => {
+            problems.push(e);
+            Ok(())
+        }
+        result => result,
+    };
+    let source = lookup(class)?;
+    let scratch = Scratch::default();
+    let into = random_path();
+    scratch.add(into.clone());
+    let package = match &source {
+        Source::Git {
+            url,
+            branch,
+            package,
+        } => {
+            clone(
+                url,
+                branch,
+                None,
+                None,
+                &into,
+                &mut |_| {},
+                false,
+                &[],
+                network,
+            )?;
+            if Repository::open(&into)?.is_empty().unwrap_or(false) {
+                say!("{} has no commits yet", url);
+                return Err(Error::EmptyTemplate);
+            }
+            package.clone()
+        }
+        Source::Archive(archive) => Some(extract(archive, &into)?),
+        Source::Release(release) => {
+            let archive = into.with_extension("download");
+            scratch.add(archive.clone());
+            download_release(release, &archive, network)?;
+            Some(extract(&archive, &into)?)
+        }
+        Source::Local { dir, package } => {
+            copy_local(dir, &into)?;
+            package.clone().or(Some(String::new()))
+        }
+    };
+    let manifest = match read_manifest(&into) {
+        Ok(manifest) => manifest,
+        Err(e) => {
+            report(Err(e))?;
+            Manifest::default()
+        }
+    };
+    let package = match &source {
+        Source::Archive(_) | Source::Release(_) => package,
+        _ => manifest.package.clone().or(package),
+    };
+    match package {
+        None => {
+            say!(
+                "the template names no package, add `package` to its {}",
+                MANIFEST
+            );
+            report(Err(Error::NoPackage))?;
+        }
+        Some(package) => report(confined(&into, package).and_then(|package| {
+            let root = into.join(&package);
+            if !root.is_dir() {
+                say!("the template has no `{}` directory", package);
+                return Err(Error::NoPackage);
+            }
+            let manifest = std::fs::read_to_string(root.join("Cargo.toml")).map_err(|e| {
+                say!("{}: {}", root.join("Cargo.toml").display(), e);
+                Error::InvalidCargoToml
+            })?;
+            toml::from_str::<toml::Table>(&manifest).map_err(|e| {
+                say!("the package's Cargo.toml: {}", e);
+                Error::InvalidCargoToml
+            })?;
+            Ok(())
+        }))?,
+    }
+    if let Some(Err(e)) = manifest.base.as_deref().map(lookup) {
+        say!("{}: `base` is not a class", MANIFEST);
+        report(Err(e))?;
+    }
+    for path in manifest.conditional.values().flatten() {
+        let relative = Path::new(path)
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)));
+        if !relative {
+            say!("{}: `{}` must be relative to the package", MANIFEST, path);
+            report(Err(Error::InvalidManifest))?;
+        }
+    }
+    for pattern in manifest.minimal.iter().flatten() {
+        if let Err(e) = Glob::new(pattern) {
+            say!("{}: `{}` in `minimal`: {}", MANIFEST, pattern, e);
+            report(Err(Error::InvalidGlob))?;
+        }
+    }
+    match problems.into_iter().next() {
+        Some(e) => Err(e),
+        None => {
+            say!("{} is fine", class);
+            Ok(())
+        }
+    }
+}
+
+/// Prints where CLASS is defined, `templates.toml` or the built-in classes,
+/// along with what it resolves to, in the formats of `list`.
+fn which(class: &str, format: &str) -> Result<(), Error> {
+    let overrides = read_registry()?.templates.contains_key(class);
+    let source = match (overrides, URLS.contains_key(class)) {
+        (true, builtin) => {
+            let path = registry_path().unwrap().display().to_string();
+            match builtin {
+                true => format!("{}, replacing the built-in class", path),
+                false => path,
+            }
+        }
+        (false, true) => "built-in".to_string(),
+        (false, false) if class.starts_with(RELEASE_PREFIX) => {
+            parse_release(class)?;
+            "GitHub release".to_string()
+        }
+        (false, false) if Path::new(class).is_file() => "archive".to_string(),
+        (false, false) => {
+            say!("no class is named `{}`", class);
+            return Err(Error::NoSuchClass);
+        }
+    };
+    let Some(template) = templates()?.into_iter().find(|t| t.name == class) else {
+        // an archive or a release is its own descriptor
+        match format {
+            "json" => say!("{}", serde_json::json!({ "name": class, "source": source })),
+            "plain" => say!("{}", source),
+            _ => print_table(&[["NAME", "SOURCE"], [class, &source]]),
+        }
+        return Ok(());
+    };
+    match format {
+        "json" => {
+            let mut json = serde_json::to_value(&template).unwrap();
+            json["source"] = source.into();
+            say!("{}", serde_json::to_string_pretty(&json).unwrap());
+        }
+        "plain" => say!("{}", source),
+        _ => {
+            let tags = match template.tags.is_empty() {
+                true => "-".to_string(),
+                false => template.tags.join(","),
+            };
+            print_table(&[
+                ["NAME", "URL", "BRANCH", "PACKAGE", "TAGS", "SOURCE"],
+                [
+                    &template.name,
+                    &template.url,
+                    &template.branch,
+                    template.package.as_deref().unwrap_or("-"),
+                    &tags,
+                    &source,
+                ],
+            ])
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug)]
+struct Context {
+    class: String,
+    source: Source,
+    tmp_path: PathBuf,
+    /// The destination, absolute, with its `.` and `..` resolved.
+    path: PathBuf,
+    /// A fresh directory under the current directory the package is copied into
+    /// before being renamed to `path`, so an existing directory named after
+    /// the package is never touched.
+    staging_path: PathBuf,
+    /// What to remove once the run is over: `staging_path`, `tmp_path` unless
+    /// it is kept, and whatever else the run sets aside.
+    scratch: Scratch,
+    /// The crate name written to Cargo.toml, also available as `{{project_name}}`.
+    project_name: String,
+    vars: HashMap<String, String>,
+    /// The refspecs `--rev` stands for, tried in order until one fetches something.
+    rev: Option<Vec<String>>,
+    /// A directory inside the package whose contents become the project root.
+    strip_prefix: Option<String>,
+    /// An SPDX expression whose license text replaces the template's.
+    license: Option<String>,
+    force: bool,
+    checkout_submodules: bool,
+    min_free_space: Option<u64>,
+    verify_signature: bool,
+    trace_timings: bool,
+    /// Files larger than this many bytes are left out of substitution.
+    max_file_size: u64,
+    verbose: bool,
+    /// Fetch git templates through the clone cache.
+    cache: bool,
+    /// Limits substitution to the files matching these globs, relative to the project root.
+    replace_in: Option<GlobSet>,
+    /// Print the commit that was checked out, for the record.
+    template_ref_info: bool,
+    /// What to do when the destination already exists.
+    dest_exists: DestExists,
+    /// Leave the clone in `tmp_path` behind for inspection.
+    keep_tmp: bool,
+    /// Print the clone's progress at most this often, `None` when it is off,
+    /// as it is when stdout isn't a terminal.
+    progress: Option<Duration>,
+    /// Applied to base templates as well.
+    mirror: Option<String>,
+    /// Fail on whatever is otherwise a warning, see `warn`: a Cargo.toml that
+    /// doesn't parse, cruft, `--fmt` failing, history or a remote left out.
+    strict: bool,
+    /// For `authors` in Cargo.toml and the initial commit, see `identity`.
+    author: Option<String>,
+    email: Option<String>,
+    /// Make the project a git repository with an initial commit.
+    git_init: bool,
+    /// Report what would be written instead of writing it.
+    dry_run: bool,
+    /// Fetch every branch of the template, not just the one checked out.
+    fetch_all_branches: bool,
+    /// Keep the files in `CRUFT`, which are otherwise left out.
+    no_cruft_filter: bool,
+    /// The package to use instead of the one the template names.
+    package: Option<String>,
+    /// Directories of the template to look for the package in, see `find_package`.
+    template_subpath: Option<GlobMatcher>,
+    /// The variables `--set-from-file` defines, which `--strict` checks the
+    /// template uses.
+    file_vars: Vec<String>,
+    /// For the `--git-init` commit, instead of one naming the template.
+    commit_message: Option<String>,
+    /// Give the project's files the template's modification times, rather
+    /// than the time they were copied.
+    preserve_timestamps: bool,
+    /// The files of `RENAME_SCOPES` to rename the package in, besides Cargo.toml.
+    rename_scope: Vec<String>,
+    /// The branch the `--git-init` commit is on.
+    branch_from_rev: Option<String>,
+    /// Where classes, bases included, are looked up instead of the registry.
+    index: Option<PathBuf>,
+    /// Cut the package loose of the template's workspace, see `make_standalone`.
+    exclude_package_metadata: bool,
+    /// How long to wait for another run using the same cache entry, `None`
+    /// for as long as it takes.
+    wait_for_lock: Option<Duration>,
+    /// Leave the template's Cargo.toml as it is: no name, authors or license.
+    no_rewrite: bool,
+    /// Leave `{{...}}` tokens in the template's files as they are.
+    no_substitute: bool,
+    /// `--substitution-mode marker`: only substitute in the files that start
+    /// with the `MARKER` line.
+    marker: bool,
+    /// Keep only what `MINIMAL`, or the manifest's `minimal`, matches of the package.
+    minimal: bool,
+    /// Globs `--minimal` keeps on top of those.
+    minimal_include: Vec<String>,
+    /// Print what the run is about to do before doing it.
+    print_plan: bool,
+    /// Print what is meant for tools as JSON.
+    json: bool,
+    /// Run `cargo fmt` in the project once it is written.
+    fmt: bool,
+    /// Run `cargo check` in the project last, failing the run if it fails.
+    check: bool,
+    /// Only print the README of the template's package.
+    template_readme: bool,
+    /// `--keep-git` or `--no-keep-git`, over what the template's manifest says.
+    keep_git: Option<bool>,
+    /// Only clone the template's repository into this directory.
+    clone_only: Option<PathBuf>,
+    /// Scaffold a package with no files rather than fail.
+    allow_empty: bool,
+    /// Print the project's files as a tree once it is created.
+    tree: bool,
+    /// `--engine liquid`: render the `.liquid` files with liquid, as
+    /// cargo-generate does, instead of substituting tokens in them.
+    liquid: bool,
+    /// With `--source-ref-lock`, the template's url to write to
+    /// `contemplate.lock`, the one of the registry rather than of `--mirror`.
+    source_ref_lock: Option<String>,
+    /// With `--locked`, the commit `contemplate.lock` pins the template to.
+    locked: Option<String>,
+    /// With `--worktree`, the `.git` of the repository the project becomes a
+    /// worktree of.
+    worktree: Option<PathBuf>,
+    /// Copy every file of the package to the top of the project.
+    flatten: bool,
+    /// Path specs limiting what of the template's repository is checked out.
+    checkout_path_spec: Vec<String>,
+    /// What `origin` points at in a kept history instead of the template.
+    rename_git_remote: Option<String>,
+    /// Leave a kept history without `origin`.
+    clear_git_remote: bool,
+    /// `--no-network` and `--proxy`.
+    network: Network,
+}
+
+const MANIFEST: &str = "contemplate.toml";
+
+/// Optional settings a template declares in a `contemplate.toml` at the root
+/// of its repository (or archive), next to the package directory.
+#[derive(Debug, Default, Deserialize)]
+struct Manifest {
+    /// The directory holding the package, so registry entries don't have
+    /// to know the template's layout.
+    package: Option<String>,
+    /// Printed once the project has been created, e.g. "now run `cargo build`".
+    post_create_message: Option<String>,
+    /// Maps a variable to package paths that are left out when the variable
+    /// is set to a false value, e.g. `docker = ["Dockerfile"]`.
+    #[serde(default)]
+    conditional: HashMap<String, Vec<String>>,
+    /// A class whose package is laid down first, this template's files going
+    /// over it.
+    base: Option<String>,
+    /// What `--minimal` keeps of the package instead of `MINIMAL`, as globs.
+    minimal: Option<Vec<String>>,
+    /// Whether the project starts out with the template's git history, for
+    /// templates that are a whole repository, unless the user says otherwise.
+    keep_git: Option<bool>,
+}
+
+fn is_false(value: &str) -> bool {
+    matches!(
+        value.to_ascii_lowercase().as_str(),
+        "false" | "no" | "off" | "0" | ""
+    )
+}
+
+/// Removes the paths the manifest excludes for the current variables from
+/// the package.
+fn apply_conditionals(
+    manifest: &Manifest,
+    vars: &HashMap<String, String>,
+    package: &Path,
+) -> Result<(), Error> {
+    for (var, paths) in &manifest.conditional {
+        if !vars.get(var).is_some_and(|value| is_false(value)) {
+            continue;
+        }
+        for path in paths {
+            if !Path::new(path)
+                .components()
+                .all(|c| matches!(c, Component::Normal(_)))
+            {
+                say!("{}: `{}` must be relative to the package", MANIFEST, path);
+                return Err(Error::InvalidManifest);
+            }
+            let path = package.join(path);
+            let removed = if path.is_dir() {
+                std::fs::remove_dir_all(&path)
+            } else {
+                std::fs::remove_file(&path)
+            };
+            match removed {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                    say!("{}", e);
+                    return Err(Error::FileSystemFault);
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(())
+}
+
+fn read_manifest(root: &Path) -> Result<Manifest, Error> {
+    let content = match std::fs::read_to_string(root.join(MANIFEST)) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Manifest::default()),
+        Err(e) => {
+            say!("{}", e);
+            return Err(Error::FileSystemFault);
+        }
+    };
+    toml::from_str(&content).map_err(|e| {
+        say!("{}: {}", MANIFEST, e);
+        Error::InvalidManifest
+    })
+}
+
+/// The template CLASS names, in a local directory with `--from-local` or
+/// looked up in `--index` or the registry.
+fn requested_source(args: &Args) -> Result<Source, Error> {
+    match args.from_local {
+        true if Path::new(&args.class).is_dir() => Ok(Source::Local {
+            dir: PathBuf::from(&args.class),
+            package: None,
+        }),
+        true => {
+            say!("`{}` is not a directory", args.class);
+            Err(Error::NoSuchClass)
+        }
+        false => lookup_in(&args.class, args.index.as_deref()),
+    }
+}
+
+/// `source` as it is fetched, `--mirror` and `--branch` applied.
+fn fetched_source(source: Source, args: &Args) -> Result<Source, Error> {
+    Ok(match source {
+        Source::Git {
+            url,
+            branch,
+            package,
+        } => Source::Git {
+            url: match &args.mirror {
+                Some(mirror) => mirrored(&url, mirror, args.strict)?,
+                None => url,
+            },
+            branch: local_branch(args.branch.as_deref().unwrap_or(&branch)).to_string(),
+            package,
+        },
+        source => source,
+    })
+}
+
+impl TryFrom<Args> for Context {
+    type Error = Error;
+    fn try_from(mut args: Args) -> Result<Self, Self::Error> {
+        // `--set` wins over the file
+        let mut file_vars = Vec::new();
+        if let Some(file) = &args.vars_file {
+            let mut vars = read_vars(file)?;
+            file_vars = vars
+                .keys()
+                .filter(|name| {
+                    *name != "project_name"
+                        && !args.dest.to_str().is_some_and(|dest| mentions(dest, name))
+                })
+                .cloned()
+                .collect();
+            file_vars.sort();
+            vars.extend(args.vars);
+            args.vars = vars;
+        }
+        let source = requested_source(&args)?;
+        let registry_url = match &source {
+            Source::Git { url, .. } => Some(url.clone()),
+            _ => None,
+        };
+        let locked = match args.locked {
+            true => {
+                let lock = read_lock(Path::new(LOCKFILE))?;
+                if lock.name != args.class || registry_url.as_ref() != Some(&lock.url) {
+                    say!(
+                        "{} pins {} at {}, not {}",
+                        LOCKFILE,
+                        lock.name,
+                        lock.url,
+                        registry_url.as_deref().unwrap_or(&args.class)
+                    );
+                    return Err(Error::InvalidLock);
+                }
+                args.rev = Some(lock.commit.clone());
+                args.ref_type = Some("commit".to_string());
+                Some(lock.commit)
+            }
+            false => None,
+        };
+        let source_ref_lock = match (args.source_ref_lock, registry_url) {
+            (true, None) => {
+                warn(
+                    args.strict,
+                    &format!(
+                        "`{}` is not a git template, there is no commit to lock",
+                        args.class
+                    ),
+                )?;
+                None
+            }
+            (true, url) => url,
+            (false, _) => None,
+        };
+        let source = fetched_source(source, &args)?;
+        let current_dir = env::current_dir()?;
+        let base = match args.relative_to_repo_root {
+            true => {
+                let repo = Repository::discover(&current_dir).map_err(|_| {
+                    say!("--relative-to-repo-root needs to be run inside a git repository");
+                    Error::NotInRepository
+                })?;
+                let root = repo.workdir().ok_or_else(|| {
+                    say!("{} is a bare repository", repo.path().display());
+                    Error::NotInRepository
+                })?;
+                root.to_path_buf()
+            }
+            false => current_dir.clone(),
+        };
+        let worktree = match args.worktree {
+            true => match Repository::discover(&current_dir) {
+                Ok(repo) => Some(repo.path().to_path_buf()),
+                Err(_) => {
+                    say!("--worktree needs to be run inside a git repository");
+                    return Err(Error::NotInRepository);
+                }
+            },
+            false => None,
+        };
+        // everything from here on works on the absolute path, so that `.`,
+        // `..` and nested destinations mean what they say
+        let dest = match args.dest.to_str() {
+            Some(dest) => PathBuf::from(resolve_dest(dest, &args.vars)?),
+            None => args.dest.clone(),
+        };
+        let mut path = absolute(&base, &dest);
+        let dir_name = match path.file_name() {
+            Some(name) => name.to_string_lossy().into_owned(),
+            None => {
+                say!("`{}` is not a valid destination", args.dest.display());
+                return Err(Error::InvalidDest);
+            }
+        };
+        let prefix = args.prefix.unwrap_or_default();
+        let given = args.vars.get("project_name");
+        let project_name = format!("{}{}", prefix, given.unwrap_or(&dir_name));
+        if !is_crate_name(&project_name) {
+            match given {
+                Some(_) => say!("`{}` is not a valid crate name", project_name),
+                None => say!(
+                    "`{}` is not a valid crate name, name the crate with --set project_name=<NAME>",
+                    project_name
+                ),
+            }
+            return Err(Error::InvalidCrateName);
+        }
+        if args.prefix_dir {
+            path = path.with_file_name(format!("{}{}", prefix, dir_name));
+        }
+        let mut vars = args.vars;
+        vars.insert("project_name".to_string(), project_name.clone());
+
+        if let Some(branch) = &args.branch_from_rev {
+            if !git2::Branch::name_is_valid(branch).unwrap_or(false) {
+                say!("`{}` is not a valid branch name", branch);
+                return Err(Error::InvalidRev);
+            }
+        }
+        let rev = match &args.rev {
+            Some(rev) => Some(rev_candidates(rev, args.ref_type.as_deref())?),
+            None => None,
+        };
+        let replace_in = match args.replace_in.is_empty() {
+            true => None,
+            false => {
+                let mut globs = GlobSetBuilder::new();
+                for glob in &args.replace_in {
+                    globs.add(Glob::new(glob).map_err(|e| {
+                        say!("{}", e);
+                        Error::InvalidGlob
+                    })?);
+                }
+                Some(globs.build().map_err(|e| {
+                    say!("{}", e);
+                    Error::InvalidGlob
+                })?)
+            }
+        };
+
+        let template_subpath = match &args.template_subpath {
+            Some(glob) => Some(
+                GlobBuilder::new(glob)
+                    .literal_separator(true)
+                    .build()
+                    .map_err(|e| {
+                        say!("{}", e);
+                        Error::InvalidGlob
+                    })?
+                    .compile_matcher(),
+            ),
+            None => None,
+        };
+
+        let tmp_path = random_path();
+        let staging_path = current_dir.join(format!(".contemplate-{}", random_name()));
+        let scratch = Scratch::default();
+        scratch.add(staging_path.clone());
+        if !args.keep_tmp {
+            scratch.add(tmp_path.clone());
+        }
+
+        Ok(Context {
+            source,
+            tmp_path,
+            path,
+            staging_path,
+            scratch,
+            project_name,
+            vars,
+            rev,
+            strip_prefix: args.strip_prefix,
+            license: args.license,
+            force: args.force,
+            checkout_submodules: args.checkout_submodules,
+            min_free_space: args.min_free_space,
+            verify_signature: args.verify_signature,
+            trace_timings: args.trace_timings,
+            max_file_size: args.max_file_size.saturating_mul(1024 * 1024),
+            verbose: args.verbose,
+            cache: args.cache,
+            replace_in,
+            template_ref_info: args.template_ref_info,
+            dest_exists: args.dest_exists,
+            keep_tmp: args.keep_tmp,
+            progress: (!args.no_progress && io::stdout().is_terminal())
+                .then(|| Duration::from_millis(args.progress_interval)),
+            mirror: args.mirror,
+            strict: args.strict,
+            author: args.author,
+            email: args.email,
+            git_init: args.git_init,
+            dry_run: args.dry_run,
+            fetch_all_branches: args.fetch_all_branches,
+            no_cruft_filter: args.no_cruft_filter,
+            package: args.package,
+            template_subpath,
+            file_vars,
+            commit_message: args.commit_message,
+            branch_from_rev: args.branch_from_rev,
+            index: args.index,
+            exclude_package_metadata: args.exclude_package_metadata,
+            wait_for_lock: args.wait_for_lock.map(Duration::from_secs),
+            no_rewrite: args.no_rewrite,
+            no_substitute: args.no_substitute,
+            marker: args.marker,
+            minimal: args.minimal,
+            minimal_include: args.minimal_include,
+            print_plan: args.print_plan,
+            json: args.json,
+            fmt: args.fmt,
+            check: args.check,
+            template_readme: args.template_readme,
+            keep_git: args.keep_git,
+            clone_only: match &args.clone_only {
+                Some(dir) => Some(absolute(
+                    &current_dir,
+                    Path::new(&expand(&dir.to_string_lossy())?),
+                )),
+                None => None,
+            },
+            allow_empty: args.allow_empty,
+            tree: args.tree,
+            liquid: args.liquid,
+            source_ref_lock,
+            locked,
+            worktree,
+            flatten: args.flatten,
+            checkout_path_spec: args.checkout_path_spec,
+            rename_git_remote: args.rename_git_remote,
+            clear_git_remote: args.clear_git_remote,
+            network: Network::new(args.no_network, args.proxy)?,
+            preserve_timestamps: args.preserve_timestamps,
+            rename_scope: match args.rename_scope.iter().any(|scope| scope == "all") {
+                true => RENAME_SCOPES.map(String::from).to_vec(),
+                false => args.rename_scope,
+            },
+            class: args.class,
+        })
+    }
+}
+
+/// The progress of a run, for the caller of [`scaffold`] to show as it likes
+/// instead of the built-in display.
+#[derive(Debug, Clone)]
+pub enum ProgressEvent {
+    /// The objects of the template's repository received and indexed so far.
+    Fetch(FetchProgress),
+    /// A line of what the server says while it prepares the pack, e.g.
+    /// `Counting objects: 50% (1/2)`, `done` once its phase is over.
+    Remote { message: String, done: bool },
+    /// The file being checked out of the clone, `current` of `total`.
+    Checkout {
+        path: Option<PathBuf>,
+        current: usize,
+        total: usize,
+    },
+    /// The bytes of a template copied into the project so far, each base's
+    /// in turn and then the template's own.
+    Copy { copied_bytes: u64, total_bytes: u64 },
+}
+
+/// How far a fetch has come, as libgit2 counts it.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FetchProgress {
+    pub received_objects: usize,
+    pub indexed_objects: usize,
+    pub total_objects: usize,
+    pub received_bytes: usize,
+    pub indexed_deltas: usize,
+    pub total_deltas: usize,
+}
+
+/// What the built-in display keeps of the events so far.
+struct State {
+    /// How often to print at most, `None` not to print at all.
+    interval: Option<Duration>,
+    last_print: Option<Instant>,
+    fetch: Option<FetchProgress>,
+    total: usize,
+    current: usize,
+    path: Option<PathBuf>,
+    newline: bool,
+    /// Whether the transfer line is being updated, which a server message
+    /// then goes below of.
+    transferring: bool,
+}
+
+/// The built-in display of a run's progress, printed at most every
+/// `interval`, or not at all without one.
+fn printer(interval: Option<Duration>) -> impl FnMut(ProgressEvent) {
+    let mut state = State {
+        interval,
+        last_print: None,
+        fetch: None,
+        total: 0,
+        current: 0,
+        path: None,
+        newline: false,
+        transferring: false,
+    };
+    move |event| match event {
+        ProgressEvent::Fetch(fetch) => {
+            state.fetch = Some(fetch);
+            print(&mut state);
+        }
+        ProgressEvent::Remote { message, done } => print_remote(&mut state, &message, done),
+        ProgressEvent::Checkout {
+            path,
+            current,
+            total,
+        } => {
+            state.path = path;
+            state.current = current;
+            state.total = total;
+            print(&mut state);
+        }
+        // over in no time next to the clone
+        ProgressEvent::Copy { .. } => {}
+    }
+}
+
+/// Splits what the server sent into the lines of its progress, `pending`
+/// holding what it sent of a line so far: one ends in `\r` while its phase
+/// goes on, in `\n` once it is done.
+fn remote_lines(pending: &mut String, data: &[u8]) -> Vec<(String, bool)> {
+    pending.push_str(&String::from_utf8_lossy(data));
+    let mut lines = Vec::new();
+    while let Some(end) = pending.find(['\r', '\n']) {
+        let line: String = pending.drain(..=end).collect();
+        lines.push((line[..end].trim_end().to_string(), line.ends_with('\n')));
+    }
+    lines
+}
+
+/// Prints what the server says while it prepares the pack, before anything
+/// is received, e.g. `Counting objects` then `Compressing objects`, the way
+/// git does: each phase updated on its line and kept once it is done.
+fn print_remote(state: &mut State, message: &str, done: bool) {
+    let Some(interval) = state.interval else {
+        return;
+    };
+    let now = Instant::now();
+    let throttled = state.last_print.is_some_and(|last| now - last < interval);
+    if message.is_empty() || (!done && (throttled || state.transferring)) {
+        return;
+    }
+    state.last_print = Some(now);
+    match done {
+        true if state.transferring => println!("\nremote: {}", message),
+        true => println!("remote: {}", message),
+        false => print!("remote: {}\r", message),
+    }
+    io::stdout().flush().unwrap();
+}
+
+fn print(state: &mut State) {
+    let Some(interval) = state.interval else {
+        return;
+    };
+    // a local clone may start checking out before reporting any transfer
+    let Some(stats) = state.fetch else {
+        return;
+    };
+    // what it ends at is always printed, whatever came just before
+    let finished = (stats.received_objects == stats.total_objects
+        && stats.indexed_deltas == stats.total_deltas)
+        || (state.total > 0 && state.current == state.total);
+    let now = Instant::now();
+    if !finished && state.last_print.is_some_and(|last| now - last < interval) {
+        return;
+    }
+    state.last_print = Some(now);
+    state.transferring = true;
+    let network_pct = (100 * stats.received_objects)
+        .checked_div(stats.total_objects)
+        .unwrap_or(100);
+    let index_pct = (100 * stats.indexed_objects)
+        .checked_div(stats.total_objects)
+        .unwrap_or(100);
+    let co_pct = (100 * state.current).checked_div(state.total).unwrap_or(0);
+    let kbytes = stats.received_bytes / 1024;
+    if stats.received_objects == stats.total_objects {
+        if !state.newline {
+            println!();
+            state.newline = true;
+        }
+        print!(
+            "Resolving deltas {}/{}\r",
+            stats.indexed_deltas, stats.total_deltas
+        );
+    } else {
+        print!(
+            "net {:3}% ({:4} kb, {:5}/{:5})  /  idx {:3}% ({:5}/{:5})  \
+             /  chk {:3}% ({:4}/{:4}) {}\r",
+            network_pct,
+            kbytes,
+            stats.received_objects,
+            stats.total_objects,
+            index_pct,
+            stats.indexed_objects,
+            stats.total_objects,
+            co_pct,
+            state.current,
+            state.total,
+            state
+                .path
+                .as_ref()
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        )
+    }
+    io::stdout().flush().unwrap();
+}
+
+/// What `--rev` is tried as. A full ref or refspec is taken as it is, unless
+/// `ref_type` says what kind of name it is. A plain name is tried as a
+/// branch, then as a tag, then, if it looks like one, as a commit sha.
+fn rev_candidates(rev: &str, ref_type: Option<&str>) -> Result<Vec<String>, Error> {
+    let is_sha = rev.len() == 40 && rev.chars().all(|c| c.is_ascii_hexdigit());
+    let candidates = match ref_type {
+        Some("branch") => vec![format!("refs/heads/{}", rev)],
+        Some("tag") => vec![format!("refs/tags/{}", rev)],
+        Some(_) if !is_sha => {
+            say!("`{}` is not a full commit sha", rev);
+            return Err(Error::InvalidRev);
+        }
+        Some(_) => vec![rev.to_string()],
+        None if rev.starts_with("refs/") || rev.contains(':') => vec![rev.to_string()],
+        None => {
+            let mut candidates = vec![format!("refs/heads/{}", rev), format!("refs/tags/{}", rev)];
+            if is_sha {
+                candidates.push(rev.to_string());
+            }
+            candidates
+        }
+    };
+    Ok(candidates)
+}
+
+/// Clones `branch` of `url`, or, when `rev` is given, fetches those refspecs
+/// in turn until one gets a commit and checks that out instead. With a `cache`, the branch
+/// is fetched into it first and cloned from there. Only what `paths` match is
+/// checked out, unless there are none. Returns how long the checkout part took.
+/// Nothing is fetched from a host `allowed_hosts` leaves out.
+#[allow(clippy::too_many_arguments)]
+fn clone(
+    url: &str,
+    branch: &str,
+    rev: Option<&[String]>,
+    cache: Option<&Path>,
+    into: &Path,
+    progress: &mut dyn FnMut(ProgressEvent),
+    all_branches: bool,
+    paths: &[String],
+    network: &Network,
+) -> Result<Duration, Error> {
+    allow_host(url)?;
+    FETCHING.store(true, Ordering::SeqCst);
+    let result = fetch_and_checkout(
+        url,
+        branch,
+        rev,
+        cache,
+        into,
+        progress,
+        all_branches,
+        paths,
+        network,
+    );
+    FETCHING.store(false, Ordering::SeqCst);
+    result
+}
+
+/// Set while a fetch is under way, when setting `CANCELLED` stops it.
+static FETCHING: AtomicBool = AtomicBool::new(false);
+/// Checked by the transfer progress callback, which aborts the fetch once set.
+static CANCELLED: AtomicBool = AtomicBool::new(false);
+/// How a run may reach the network, as `--no-network` and `--proxy` say.
+#[derive(Debug, Clone, Default)]
+struct Network {
+    /// Make whatever would reach out fail instead.
+    disabled: bool,
+    /// For git and release downloads to go through, checked to be a url.
+    proxy: Option<String>,
+}
+
+impl Network {
+    fn new(disabled: bool, proxy: Option<String>) -> Result<Network, Error> {
+        if let Some(Err(e)) = proxy.as_deref().map(Url::parse) {
+            say!("invalid --proxy {}: {}", proxy.unwrap(), e);
+            return Err(Error::CannotParseUrl);
+        }
+        Ok(Network { disabled, proxy })
+    }
+}
+
+/// The proxy of `--proxy`, if any, for libgit2 to connect through.
+fn proxy_options(network: &Network) -> git2::ProxyOptions<'_> {
+    let mut options = git2::ProxyOptions::new();
+    if let Some(proxy) = network.proxy.as_deref() {
+        options.url(proxy);
+    }
+    options
+}
+
+/// Whether `--no-network` keeps `url` from being fetched, which it does
+/// unless it's on this machine, as a `file://` url or a path is.
+fn network_disabled(url: &str, network: &Network) -> bool {
+    let local = url.starts_with("file://") || Path::new(url).exists();
+    network.disabled && !local
+}
+
+/// Fails when [`network_disabled`] for `url`, rather than let it be fetched.
+fn allow_network(url: &str, network: &Network) -> Result<(), Error> {
+    if network_disabled(url, network) {
+        say!("{} would be fetched, but --no-network is given", url);
+        return Err(Error::NetworkDisabled);
+    }
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn fetch_and_checkout(
+    url: &str,
+    branch: &str,
+    rev: Option<&[String]>,
+    cache: Option<&Path>,
+    into: &Path,
+    progress: &mut dyn FnMut(ProgressEvent),
+    all_branches: bool,
+    paths: &[String],
+    network: &Network,
+) -> Result<Duration, Error> {
+    let progress = RefCell::new(progress);
+    let emit = |event| (*progress.borrow_mut())(event);
+    let remote = RefCell::new(String::new());
+    let checkout_started = Cell::new(None);
+    let mut cb = RemoteCallbacks::new();
+    credentials(&mut cb);
+    cb.transfer_progress(|stats| {
+        emit(ProgressEvent::Fetch(FetchProgress {
+            received_objects: stats.received_objects(),
+            indexed_objects: stats.indexed_objects(),
+            total_objects: stats.total_objects(),
+            received_bytes: stats.received_bytes(),
+            indexed_deltas: stats.indexed_deltas(),
+            total_deltas: stats.total_deltas(),
+        }));
+        !CANCELLED.load(Ordering::SeqCst)
+    });
+    cb.sideband_progress(|data| {
+        for (message, done) in remote_lines(&mut remote.borrow_mut(), data) {
+            emit(ProgressEvent::Remote { message, done });
+        }
+        !CANCELLED.load(Ordering::SeqCst)
+    });
+
+    let mut co = CheckoutBuilder::new();
+    co.progress(|path, current, total| {
+        if checkout_started.get().is_none() {
+            checkout_started.set(Some(Instant::now()));
+        }
+        emit(ProgressEvent::Checkout {
+            path: path.map(|p| p.to_path_buf()),
+            current,
+            total,
+        });
+    });
+    for path in paths {
+        co.path(path);
+    }
+
+    let mut fo = FetchOptions::new();
+    fo.remote_callbacks(cb);
+    fo.proxy_options(proxy_options(network));
+    // only the branch that is checked out, unless asked for all of them
+    let tracking = match all_branches {
+        true => "+refs/heads/*:refs/remotes/origin/*".to_string(),
+        false => format!("+refs/heads/{0}:refs/remotes/origin/{0}", branch),
+    };
+    let rev = match rev {
+        Some(rev) => rev,
+        None => {
+            let source = match cache {
+                Some(cache) => {
+                    say!("{} -> {}", url, cache.display());
+                    fetch_cached(url, branch, cache, all_branches, &mut fo, network)?;
+                    fo = FetchOptions::new();
+                    cache.to_string_lossy().into_owned()
+                }
+                None => {
+                    allow_network(url, network)?;
+                    url.to_string()
+                }
+            };
+            say!("{} -> {}", source, into.display());
+            RepoBuilder::new()
+                .fetch_options(fo)
+                .with_checkout(co)
+                .remote_create(|repo, name, url| repo.remote_with_fetch(name, url, &tracking))
+                .branch(branch)
+                .clone(&source, into)
+                .map_err(|e| fetch_fault(url, e, network))?;
+            return Ok(checkout_time(&checkout_started));
+        }
+    };
+
+    allow_network(url, network)?;
+    say!("{} -> {}", url, into.display());
+    let repo = Repository::init(into)?;
+    let mut fetched = None;
+    for refspec in rev {
+        repo.remote_anonymous(url)
+            .and_then(|mut remote| remote.fetch(&[refspec], Some(&mut fo), None))
+            .map_err(|e| fetch_fault(url, e, network))?;
+        if let Ok(commit) = repo
+            .find_reference("FETCH_HEAD")
+            .and_then(|head| head.peel_to_commit())
+        {
+            fetched = Some(commit);
+            break;
+        }
+    }
+    let commit = fetched.ok_or_else(|| {
+        match rev {
+            // as asked for, rather than one of the guesses at a plain name
+            [full] if full.starts_with("refs/") => {
+                say!("{} has no `{}`, or it points at no commit", url, full)
+            }
+            _ => say!("`{}` did not fetch anything", rev.join("`, `")),
+        }
+        Error::NothingFetched
+    })?;
+    if all_branches {
+        repo.remote("origin", url)
+            .and_then(|mut remote| remote.fetch(&[&tracking], Some(&mut fo), None))
+            .map_err(|e| fetch_fault(url, e, network))?;
+    }
+    repo.checkout_tree(commit.as_object(), Some(&mut co))
+        .and_then(|_| repo.set_head_detached(commit.id()))?;
+    Ok(checkout_time(&checkout_started))
+}
+
+/// Fetches `branch` of `url` into the bare repository at `cache`, creating it
+/// on first use. Templates living on different branches of one repository
+/// share the entry, each branch fetched into it next to the others, or all of
+/// them at once with `all_branches`.
+///
+/// An entry left behind by an interrupted fetch is reused as it is, so only
+/// what it misses is downloaded again, unless it is damaged, in which case it
+/// is thrown away and fetched afresh.
+///
+/// Branches come with their whole history: libgit2 1.5 can't fetch shallow,
+/// let alone deepen or add a shallow branch to an entry later. What another
+/// branch shares with the ones already fetched isn't downloaded again though.
+///
+/// Under `--no-network`, an entry that has the branch is used as it is, and
+/// one that doesn't is an error.
+fn fetch_cached(
+    url: &str,
+    branch: &str,
+    cache: &Path,
+    all_branches: bool,
+    fo: &mut FetchOptions,
+    network: &Network,
+) -> Result<(), Error> {
+    let head = format!("refs/heads/{}", branch);
+    let repo = match Repository::open_bare(cache) {
+        Ok(repo) if is_intact(&repo) && network_disabled(url, network) => {
+            if repo.find_reference(&head).is_err() {
+                say!("the cache has no {} of {}", branch, url);
+                return Err(Error::NetworkDisabled);
+            }
+            return Ok(repo.set_head(&head)?);
+        }
+        Ok(repo) if is_intact(&repo) => repo,
+        opened => {
+            allow_network(url, network)?;
+            if opened.is_ok() || cache.exists() {
+                say!("{} is damaged, fetching it again", cache.display());
+                cleanup(&[cache.to_path_buf()]);
+            }
+            Repository::init_bare(cache)?
+        }
+    };
+    let refspec = match all_branches {
+        true => "+refs/heads/*:refs/heads/*".to_string(),
+        false => format!("+{0}:{0}", head),
+    };
+    repo.remote_anonymous(url)
+        .and_then(|mut remote| remote.fetch(&[&refspec], Some(fo), None))
+        // cloning from the entry needs its HEAD to point at something
+        .and_then(|_| repo.set_head(&head))
+        .map_err(|e| fetch_fault(url, e, network))
+}
+
+/// Locks the cache `entry` so that concurrent runs take turns with it, waiting
+/// up to `wait` for the run that has it, or for as long as it takes.
+fn lock_cache(entry: &Path, wait: Option<Duration>) -> Result<std::fs::File, Error> {
+    let path = entry.with_extension("lock");
+    let file = std::fs::create_dir_all(entry.parent().unwrap())
+        .and_then(|_| {
+            std::fs::File::options()
+                .create(true)
+                .truncate(false)
+                .write(true)
+                .open(&path)
+        })
+        .map_err(|e| {
+            say!("{}: {}", path.display(), e);
+            Error::FileSystemFault
+        })?;
+    let started = Instant::now();
+    let mut told = false;
+    while fs2::FileExt::try_lock_exclusive(&file).is_err() {
+        if wait.is_some_and(|wait| started.elapsed() >= wait) {
+            say!(
+                "gave up waiting for {} after {:?}, another run is still using it",
+                entry.display(),
+                wait.unwrap()
+            );
+            return Err(Error::LockTimeout);
+        }
+        if !told {
+            say!(
+                "waiting for another run to be done with {}",
+                entry.display()
+            );
+            told = true;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    Ok(file)
+}
+
+/// Tells a failed fetch of `url` that the progress callback aborted, or that
+/// found nothing to fetch, from any other git error. Not every transport
+/// reports an abort as a user error, hence the look at `CANCELLED` as well.
+fn fetch_fault(url: &str, e: git2::Error, network: &Network) -> Error {
+    if e.code() == git2::ErrorCode::User || CANCELLED.load(Ordering::SeqCst) {
+        say!("cancelled");
+        return Error::Cancelled;
+    }
+    if is_empty_remote(url, network) {
+        say!("{} has no commits yet", url);
+        return Error::EmptyTemplate;
+    }
+    say!("{}", e);
+    Error::GitFault
+}
+
+/// Whether every ref of `repo` leads to a commit that is actually there.
+fn is_intact(repo: &Repository) -> bool {
+    repo.references()
+        .is_ok_and(|mut refs| refs.all(|r| r.and_then(|r| r.peel_to_commit()).is_ok()))
+}
+
+/// Where `url` is cached: its last path segment, for the humans, and a hash
+/// of the whole url, so that every repository gets exactly one entry.
+fn cache_entry(url: &str) -> Option<PathBuf> {
+    // FNV-1a, which unlike `DefaultHasher` is stable across Rust releases
+    let hash = url.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+    let name = url
+        .trim_end_matches('/')
+        .rsplit(['/', ':'])
+        .next()
+        .unwrap_or_default()
+        .trim_end_matches(".git");
+    cache_dir().map(|dir| dir.join(format!("{}-{:016x}.git", name, hash)))
+}
+
+/// `cache` in `$CONTEMPLATE_HOME`, or `cargo-contemplate` in the platform's cache directory.
+fn cache_dir() -> Option<PathBuf> {
+    match env::var_os("CONTEMPLATE_HOME") {
+        Some(home) => Some(PathBuf::from(home).join("cache")),
+        None => dirs::cache_dir().map(|dir| dir.join("cargo-contemplate")),
+    }
+}
+
+/// Whether `url` can be reached but has nothing to clone. libgit2 reports a
+/// missing default branch when the remote advertises no refs, which is
+/// cheaper than listing them (and `Remote::list` is unsound on an empty list).
+fn is_empty_remote(url: &str, network: &Network) -> bool {
+    let mut cb = RemoteCallbacks::new();
+    credentials(&mut cb);
+    Remote::create_detached(url)
+        .and_then(|mut remote| {
+            let mut connection =
+                remote.connect_auth(Direction::Fetch, Some(cb), Some(proxy_options(network)))?;
+            connection.remote().default_branch().map(|_| ())
+        })
+        .is_err_and(|e| e.code() == git2::ErrorCode::NotFound)
+}
+
+/// Answers a remote asking who we are: with the ssh agent for ssh, and with
+/// the login `.netrc` has for the host, or else git's credential helpers, for
+/// https. Each is offered once, as libgit2 keeps asking when one is refused.
+fn credentials(cb: &mut RemoteCallbacks) {
+    let (mut agent, mut netrc_tried, mut helper) = (false, false, false);
+    cb.credentials(move |url, username, allowed| {
+        if allowed.contains(git2::CredentialType::SSH_KEY) && !agent {
+            agent = true;
+            return git2::Cred::ssh_key_from_agent(username.unwrap_or("git"));
+        }
+        if allowed.contains(git2::CredentialType::USER_PASS_PLAINTEXT) {
+            if !netrc_tried {
+                netrc_tried = true;
+                let host = Url::parse(url)
+                    .ok()
+                    .and_then(|u| u.host_str().map(String::from));
+                if let Some((login, password)) = host.and_then(|host| netrc(&host)) {
+                    return git2::Cred::userpass_plaintext(&login, &password);
+                }
+            }
+            if !helper {
+                helper = true;
+                let config = git2::Config::open_default()?;
+                return git2::Cred::credential_helper(&config, url, username);
+            }
+        }
+        Err(git2::Error::from_str("no credentials were accepted"))
+    });
+}
+
+/// The login and password for `host` in `$NETRC`, or `~/.netrc`.
+fn netrc(host: &str) -> Option<(String, String)> {
+    let path = env::var_os("NETRC")
+        .map(PathBuf::from)
+        .or_else(|| dirs::home_dir().map(|home| home.join(".netrc")))?;
+    let content = std::fs::read_to_string(path).ok()?;
+    parse_netrc(&content, host)
+}
+
+/// Reads the `machine` entry for `host` out of a netrc file, or else the
+/// `default` entry that ends it, if there is one. `macdef` bodies, which run
+/// to the next blank line, are skipped.
+fn parse_netrc(content: &str, host: &str) -> Option<(String, String)> {
+    let mut words = Vec::new();
+    let mut lines = content.lines();
+    while let Some(line) = lines.next() {
+        for word in line.split_whitespace() {
+            if word == "macdef" {
+                // the name is the rest of the line
+                lines.by_ref().find(|line| line.trim().is_empty());
+                break;
+            }
+            words.push(word);
+        }
+    }
+    let mut tokens = words.into_iter();
+    let mut matched = false;
+    let (mut login, mut password) = (None, None);
+    while let Some(token) = tokens.next() {
+        match token {
+            "machine" | "default" if matched => break,
+            "machine" => matched = tokens.next() == Some(host),
+            "default" => matched = true,
+            "login" | "password" | "account" => {
+                let value = tokens.next();
+                match token {
+                    "login" if matched => login = value,
+                    "password" if matched => password = value,
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+    Some((login?.to_string(), password?.to_string()))
+}
+
+fn checkout_time(started: &Cell<Option<Instant>>) -> Duration {
+    started
+        .get()
+        .map_or(Duration::ZERO, |started| started.elapsed())
+}
+
+/// Initializes and checks out the submodules of `repo`, and theirs in turn.
+/// The `.git` link files they leave behind would point into the temporary
+/// clone, so they are removed once a submodule is checked out.
+fn update_submodules(repo: &Repository) -> Result<(), Error> {
+    let submodules = repo.submodules()?;
+    for mut submodule in submodules {
+        say!("submodule {}", submodule.path().display());
+        submodule
+            .update(true, None)
+            .and_then(|_| submodule.open())
+            .map_err(|e| {
+                say!("{}", e);
+                Error::GitFault
+            })
+            .and_then(|repo| update_submodules(&repo))?;
+        if let Some(workdir) = repo.workdir() {
+            std::fs::remove_file(workdir.join(submodule.path()).join(".git"))?;
+        }
+    }
+    Ok(())
+}
+
+/// Checks the signature of `commit` against the signers trusted in the config
+/// directory: `allowed_signers` (the OpenSSH format git uses for
+/// `gpg.ssh.allowedSignersFile`) for SSH signatures, and the `trustedkeys.gpg`
+/// keyring for GPG ones.
+fn verify_signature(repo: &Repository, commit: Oid) -> Result<(), Error> {
+    let fault = |e: &dyn std::fmt::Display| {
+        say!("{}", e);
+        Error::BadSignature
+    };
+    let (signature, data) = repo.extract_signature(&commit, None).map_err(|_| {
+        say!("commit {} is not signed", commit);
+        Error::BadSignature
+    })?;
+    let dir = config_dir().ok_or_else(|| fault(&"no config directory to read signers from"))?;
+    let signature_path = repo.path().join("contemplate.sig");
+    let data_path = repo.path().join("contemplate.data");
+    std::fs::write(&signature_path, &*signature)
+        .and_then(|_| std::fs::write(&data_path, &*data))
+        .map_err(|e| fault(&e))?;
+
+    let verified = if signature.starts_with(b"-----BEGIN SSH SIGNATURE-----") {
+        let signers = dir.join("allowed_signers");
+        let principals = Command::new("ssh-keygen")
+            .args(["-Y", "find-principals", "-f"])
+            .arg(&signers)
+            .arg("-s")
+            .arg(&signature_path)
+            .output()
+            .map_err(|e| fault(&e))?;
+        let principal = String::from_utf8_lossy(&principals.stdout)
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .to_string();
+        principals.status.success()
+            && Command::new("ssh-keygen")
+                .args(["-Y", "verify", "-n", "git", "-f"])
+                .arg(&signers)
+                .arg("-I")
+                .arg(&principal)
+                .arg("-s")
+                .arg(&signature_path)
+                .stdin(std::fs::File::open(&data_path).map_err(|e| fault(&e))?)
+                .status()
+                .map_err(|e| fault(&e))?
+                .success()
+    } else {
+        Command::new("gpgv")
+            .arg("--keyring")
+            .arg(dir.join("trustedkeys.gpg"))
+            .arg(&signature_path)
+            .arg(&data_path)
+            .status()
+            .map_err(|e| fault(&e))?
+            .success()
+    };
+    if !verified {
+        say!("commit {} is not signed by an allowed signer", commit);
+        return Err(Error::BadSignature);
+    }
+    Ok(())
+}
+
+/// Unpacks `archive` into `into` and returns the name of its only top-level
+/// directory, which becomes the package.
+fn extract(archive: &Path, into: &Path) -> Result<String, Error> {
+    say!("{} -> {}", archive.display(), into.display());
+    let kind = archive_kind(archive)?;
+    let file = std::fs::File::open(archive)?;
+    match kind {
+        ArchiveKind::Tar => tar::Archive::new(file).unpack(into),
+        ArchiveKind::TarGz => tar::Archive::new(GzDecoder::new(file)).unpack(into),
+        ArchiveKind::Zip => zip::ZipArchive::new(file)
+            .and_then(|mut zip| zip.extract(into))
+            .map_err(io::Error::other),
+    }
+    .map_err(|e| {
+        say!("{}", e);
+        Error::InvalidArchive
+    })?;
+
+    let entries =
+        std::fs::read_dir(into).and_then(|entries| entries.collect::<Result<Vec<_>, _>>())?;
+    let entries: Vec<_> = entries
+        .into_iter()
+        .filter(|entry| entry.file_name() != MANIFEST)
+        .collect();
+    match entries.as_slice() {
+        [entry] if entry.path().is_dir() => Ok(entry.file_name().to_string_lossy().into_owned()),
+        _ => {
+            say!(
+                "an archive must contain exactly one top-level directory, besides an optional {}",
+                MANIFEST
+            );
+            Err(Error::InvalidArchive)
+        }
+    }
+}
+
+/// Wall-clock time of each phase of `run`, for `--trace-timings`.
+#[derive(Debug)]
+struct Timings {
+    phases: Vec<(&'static str, Duration)>,
+    since: Instant,
+}
+
+impl Timings {
+    fn new() -> Timings {
+        Timings {
+            phases: Vec::new(),
+            since: Instant::now(),
+        }
+    }
+
+    /// Records the time since the previous phase ended as `phase`.
+    fn lap(&mut self, phase: &'static str) {
+        self.lap_split(phase, None);
+    }
+
+    /// Like `lap`, but the last `tail` of the time is recorded as a phase of
+    /// its own, e.g. the checkout at the end of a clone.
+    fn lap_split(&mut self, phase: &'static str, tail: Option<(&'static str, Duration)>) {
+        let now = Instant::now();
+        let elapsed = now - self.since;
+        match tail {
+            Some((name, tail)) => {
+                self.phases.push((phase, elapsed.saturating_sub(tail)));
+                self.phases.push((name, tail));
+            }
+            None => self.phases.push((phase, elapsed)),
+        }
+        self.since = now;
+    }
+}
+
+/// What a successful `run` produced.
+#[derive(Debug)]
+struct RunOutcome {
+    /// The template commit that was checked out, `None` for archives.
+    commit: Option<String>,
+    path: PathBuf,
+    /// The project's files that came from the template, relative to `path`.
+    files: Vec<PathBuf>,
+    elapsed: Duration,
+    timings: Timings,
+}
+
+/// What `run` is about to do once the template is fetched, for `--print-plan`.
+#[derive(Debug, Serialize)]
+struct Plan {
+    source: String,
+    /// What `--rev` is tried as, in order.
+    rev: Option<Vec<String>>,
+    commit: Option<String>,
+    package: String,
+    base: Option<String>,
+    destination: PathBuf,
+    /// Empty with `--no-substitute`.
+    substitutions: BTreeMap<String, String>,
+    /// Paths of the package that are left out, by pattern.
+    strip: Vec<String>,
+    /// What `--minimal` keeps, when it is given.
+    keep_only: Option<Vec<String>>,
+    /// The parts of Cargo.toml that are rewritten.
+    cargo_toml: Vec<&'static str>,
+    git_init: bool,
+    /// The branch the `--git-init` commit is on, `None` without `--git-init`.
+    branch: Option<String>,
+}
+
+impl Plan {
+    fn new(ctx: &Context, manifest: &Manifest, commit: Option<&str>, package: &str) -> Plan {
+        let source = match &ctx.source {
+            Source::Git { url, branch, .. } => format!("{} ({})", url, branch),
+            Source::Archive(archive) => archive.display().to_string(),
+            Source::Release(_) => ctx.class.clone(),
+            Source::Local { dir, .. } => dir.display().to_string(),
+        };
+        let substitutions = match ctx.no_substitute {
+            true => BTreeMap::new(),
+            false => ctx.vars.clone().into_iter().collect(),
+        };
+        let mut strip: Vec<_> = manifest
+            .conditional
+            .iter()
+            .filter(|(var, _)| ctx.vars.get(*var).is_some_and(|value| is_false(value)))
+            .flat_map(|(_, paths)| paths.iter().cloned())
+            .collect();
+        strip.sort();
+        if !ctx.no_cruft_filter {
+            strip.extend(CRUFT.map(String::from));
+        }
+        let keep_only = ctx.minimal.then(|| {
+            let mut patterns = match &manifest.minimal {
+                Some(patterns) => patterns.clone(),
+                None => MINIMAL.map(String::from).to_vec(),
+            };
+            patterns.extend(ctx.minimal_include.iter().cloned());
+            patterns
+        });
+        let mut cargo_toml = Vec::new();
+        if !ctx.no_rewrite {
+            cargo_toml.push("name");
+            if ctx.author.is_some() || ctx.email.is_some() {
+                cargo_toml.push("authors");
+            }
+            if ctx.license.is_some() {
+                cargo_toml.push("license");
+            }
+            if ctx.exclude_package_metadata {
+                cargo_toml.push("workspace inheritance");
+            }
+            if ctx.minimal {
+                cargo_toml.push("missing targets");
+            }
+        }
+        Plan {
+            source,
+            rev: ctx.rev.clone(),
+            commit: commit.map(String::from),
+            package: package.to_string(),
+            base: manifest.base.clone(),
+            destination: ctx.path.clone(),
+            substitutions,
+            strip,
+            keep_only,
+            cargo_toml,
+            git_init: ctx.git_init,
+            branch: ctx
+                .git_init
+                .then(|| ctx.branch_from_rev.clone().unwrap_or_else(initial_branch)),
+        }
+    }
+
+    /// Prints the plan to stderr, so it stays out of what the run prints.
+    fn print(&self, json: bool) {
+        if json {
+            eprintln!("{}", serde_json::to_string_pretty(self).unwrap());
+            return;
+        }
+        let list = |items: &[String]| match items.is_empty() {
+            true => "nothing".to_string(),
+            false => items.join(", "),
+        };
+        let substitutions: Vec<_> = self
+            .substitutions
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect();
+        let cargo_toml: Vec<_> = self.cargo_toml.iter().map(|s| s.to_string()).collect();
+        let mut lines = vec![("source", self.source.clone())];
+        if let Some(rev) = &self.rev {
+            lines.push(("rev", rev.join(" or ")));
+        }
+        if let Some(commit) = &self.commit {
+            lines.push(("commit", commit.clone()));
+        }
+        lines.push(("package", self.package.clone()));
+        if let Some(base) = &self.base {
+            lines.push(("base", base.clone()));
+        }
+        lines.push(("destination", self.destination.display().to_string()));
+        lines.push(("substitute", list(&substitutions)));
+        lines.push(("strip", list(&self.strip)));
+        if let Some(keep_only) = &self.keep_only {
+            lines.push(("keep only", list(keep_only)));
+        }
+        lines.push(("Cargo.toml", list(&cargo_toml)));
+        if self.git_init {
+            let branch = self.branch.as_deref().unwrap_or("the default branch");
+            lines.push(("git init", branch.to_string()));
+        }
+        eprintln!("plan:");
+        for (label, value) in lines {
+            eprintln!("  {:<12} {}", label, value);
+        }
+    }
+}
+
+/// Files and directories that editors and operating systems leave behind, by
+/// file name, which templates sometimes end up committing.
+const CRUFT: [&str; 8] = [
+    ".DS_Store",
+    "Thumbs.db",
+    "desktop.ini",
+    ".idea",
+    ".vscode",
+    "*.swp",
+    "*.swo",
+    "*~",
+];
+
+/// What `--minimal` keeps of a package, unless its manifest says otherwise.
+const MINIMAL: [&str; 3] = ["Cargo.toml", "src/**", "README.md"];
+
+/// Removes everything below `dir` that none of `patterns` match, as globs
+/// relative to it, and the directories that leaves empty. A directory that is
+/// matched is kept whole.
+fn keep_minimal(dir: &Path, patterns: &[String]) -> Result<(), Error> {
+    let mut globs = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = GlobBuilder::new(pattern)
+            .literal_separator(true)
+            .build()
+            .map_err(|e| {
+                say!("{}", e);
+                Error::InvalidGlob
+            })?;
+        globs.add(glob);
+    }
+    let keep = globs.build().map_err(|e| {
+        say!("{}", e);
+        Error::InvalidGlob
+    })?;
+    prune(dir, dir, &keep)?;
+    Ok(())
+}
+
+/// `keep_minimal` for `dir`, returning whether anything was left in it.
+fn prune(top: &Path, dir: &Path, keep: &GlobSet) -> Result<bool, Error> {
+    let entries =
+        std::fs::read_dir(dir).and_then(|entries| entries.collect::<Result<Vec<_>, _>>())?;
+    let mut kept = false;
+    for entry in entries {
+        let path = entry.path();
+        if keep.is_match(path.strip_prefix(top).unwrap()) {
+            kept = true;
+            continue;
+        }
+        let removed = match path.is_dir() && !path.is_symlink() {
+            true if prune(top, &path, keep)? => {
+                kept = true;
+                continue;
+            }
+            true => std::fs::remove_dir(&path),
+            false => std::fs::remove_file(&path),
+        };
+        removed?;
+    }
+    Ok(kept)
+}
+
+/// Moves every file below `dir` to the top of it and removes the directories
+/// they were in, for `--flatten`. Fails without touching anything when two
+/// files have the same name.
+fn flatten(dir: &Path, scratch: &Scratch) -> Result<(), Error> {
+    let mut found = files(dir)?;
+    found.sort();
+    let mut names: HashMap<_, &PathBuf> = HashMap::new();
+    for file in &found {
+        if let Some(other) = names.insert(file.file_name().unwrap(), file) {
+            say!(
+                "{} and {} would both be {} once flattened",
+                other.strip_prefix(dir).unwrap().display(),
+                file.strip_prefix(dir).unwrap().display(),
+                file.file_name().unwrap().to_string_lossy()
+            );
+            return Err(Error::FileNameCollision);
+        }
+    }
+    // moved into a directory of their own first, as a file can be named
+    // like one of the directories it leaves
+    let flat = dir.with_extension("flat");
+    scratch.add(flat.clone());
+    std::fs::create_dir(&flat)?;
+    for file in &found {
+        std::fs::rename(file, flat.join(file.file_name().unwrap()))?;
+    }
+    std::fs::remove_dir_all(dir)?;
+    std::fs::rename(&flat, dir)?;
+    Ok(())
+}
+
+/// Drops the examples, benches and tests of the manifest whose files
+/// `--minimal` left out, which cargo would otherwise fail to find.
+fn drop_missing_targets(project: &Path) -> Result<(), Error> {
+    edit_manifest(project, |doc| {
+        for (kind, dir) in [
+            ("example", "examples"),
+            ("bench", "benches"),
+            ("test", "tests"),
+        ] {
+            let Some(targets) = doc.get_mut(kind).and_then(|t| t.as_array_of_tables_mut()) else {
+                continue;
+            };
+            targets.retain(|target| {
+                let name = target
+                    .get("name")
+                    .and_then(|n| n.as_str())
+                    .unwrap_or_default();
+                match target.get("path").and_then(|p| p.as_str()) {
+                    Some(path) => project.join(path).exists(),
+                    None => {
+                        project.join(dir).join(format!("{}.rs", name)).exists()
+                            || project.join(dir).join(name).join("main.rs").exists()
+                    }
+                }
+            });
+            if targets.is_empty() {
+                doc.remove(kind);
+            }
+        }
+    })
+}
+
+/// Everything below `dir` whose name is in `CRUFT`. A directory that is found
+/// isn't looked into.
+fn find_cruft(dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    let mut globs = GlobSetBuilder::new();
+    for pattern in CRUFT {
+        globs.add(Glob::new(pattern).unwrap());
+    }
+    let cruft = globs.build().unwrap();
+    let mut found = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let entries = std::fs::read_dir(&dir)?;
+        for entry in entries {
+            let path = entry?.path();
+            if cruft.is_match(path.file_name().unwrap()) {
+                found.push(path);
+            } else if path.is_dir() {
+                dirs.push(path);
+            }
+        }
+    }
+    Ok(found)
+}
+
+/// Copies the local template `dir` into `into`, where it is worked on like a
+/// clone, leaving out its `.git` if it has one.
+fn copy_local(dir: &Path, into: &Path) -> Result<(), Error> {
+    let fault = |e: &dyn std::fmt::Display| {
+        say!("{}", e);
+        Error::FileSystemFault
+    };
+    say!("{} -> {}", dir.display(), into.display());
+    std::fs::create_dir_all(into).map_err(|e| fault(&e))?;
+    let entries = std::fs::read_dir(dir)
+        .and_then(|entries| entries.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| fault(&e))?;
+    for entry in entries.iter().filter(|entry| entry.file_name() != ".git") {
+        match entry.path().is_dir() {
+            true => fs_extra::dir::copy(entry.path(), into, &CopyOptions::new())
+                .map(|_| ())
+                .map_err(|e| fault(&e))?,
+            false => std::fs::copy(entry.path(), into.join(entry.file_name()))
+                .map(|_| ())
+                .map_err(|e| fault(&e))?,
+        }
+    }
+    Ok(())
+}
+
+/// Every file below `dir`, recursively.
+fn files(dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    let mut found = Vec::new();
+    let entries = std::fs::read_dir(dir)?;
+    for entry in entries {
+        let path = entry?.path();
+        if path.is_dir() {
+            found.extend(files(&path)?);
+        } else {
+            found.push(path);
+        }
+    }
+    Ok(found)
+}
+
+/// The files under `dir` drawn as a tree, like the `tree` command does, with
+/// `dir` itself first. `.git` is listed but not descended into.
+fn file_tree(dir: &Path) -> Result<String, Error> {
+    fn draw(dir: &Path, indent: &str, out: &mut String) -> Result<(), Error> {
+        let mut entries = std::fs::read_dir(dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        entries.sort();
+        for (i, path) in entries.iter().enumerate() {
+            let last = i + 1 == entries.len();
+            let name = path.file_name().unwrap().to_string_lossy();
+            out.push_str(&format!(
+                "{}{} {}\n",
+                indent,
+                if last { "└──" } else { "├──" },
+                name
+            ));
+            if path.is_dir() && !path.is_symlink() && name != ".git" {
+                let indent = format!("{}{}", indent, if last { "    " } else { "│   " });
+                draw(path, &indent, out)?;
+            }
+        }
+        Ok(())
+    }
+    let mut out = format!("{}\n", dir.display());
+    draw(dir, "", &mut out)?;
+    Ok(out)
+}
+
+/// Replaces the `{{name}}` tokens of known variables in every text file of
+/// the project. Unknown tokens are left alone, since a template's files may
+/// well contain braces of their own, and non UTF-8 files are skipped, as are
+/// files above `max_size` bytes, without reading them. With a `scope`, only
+/// the files it matches are considered. With `liquid`, the `.liquid` files
+/// are left to `render_liquid`. With `marker`, only the files whose first
+/// line is the `MARKER` are, and the line is dropped from them.
+fn substitute(
+    project: &Path,
+    vars: &HashMap<String, String>,
+    scope: Option<&GlobSet>,
+    max_size: u64,
+    verbose: bool,
+    liquid: bool,
+    marker: bool,
+) -> Result<(), Error> {
+    for path in files(project)? {
+        let relative = path.strip_prefix(project).unwrap();
+        if scope.is_some_and(|scope| !scope.is_match(relative)) {
+            continue;
+        }
+        if liquid && is_liquid(&path) {
+            continue;
+        }
+        let size = std::fs::metadata(&path).map_or(0, |meta| meta.len());
+        if size > max_size {
+            if verbose {
+                say!(
+                    "not substituting in {} ({} bytes)",
+                    relative.display(),
+                    size
+                );
+            }
+            continue;
+        }
+        let content = match std::fs::read(&path).map(String::from_utf8) {
+            Ok(Ok(content)) if marker || content.contains("{{") => content,
+            Ok(_) => continue,
+            Err(e) => {
+                say!("{}", e);
+                return Err(Error::FileSystemFault);
+            }
+        };
+        let text = match marker {
+            true => match strip_marker(&content) {
+                Some(text) => text,
+                None => continue,
+            },
+            false => &content,
+        };
+        let substituted = fill(text, vars);
+        if substituted != content {
+            std::fs::write(&path, substituted)?;
+        }
+    }
+    Ok(())
+}
+
+/// What the first line of a file says, inside a comment of any kind, for
+/// `--substitution-mode marker` to substitute in it.
+const MARKER: &str = "contemplate: template";
+
+/// The rest of `content` when its first line is the `MARKER`, commented out
+/// with `#`, `//`, `--`, `;`, `/* */` or `<!-- -->`.
+fn strip_marker(content: &str) -> Option<&str> {
+    let line = content.split_inclusive('\n').next()?;
+    let comment = ['#', '/', '-', ';', '*', '<', '!', '>'];
+    let text = line.trim().trim_matches(comment).trim();
+    (text == MARKER).then(|| &content[line.len()..])
+}
+
+/// Whether `path` is a liquid template, by its extension.
+fn is_liquid(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == "liquid")
+}
+
+/// Renders every `.liquid` file of the project with the liquid engine and
+/// the variables, into the same path without the extension. The rest of the
+/// files aren't liquid templates and are left alone.
+fn render_liquid(project: &Path, vars: &HashMap<String, String>) -> Result<(), Error> {
+    let parser = liquid::ParserBuilder::with_stdlib().build().map_err(|e| {
+        say!("{}", e);
+        Error::TemplateRender
+    })?;
+    let globals: liquid::Object = vars
+        .iter()
+        .map(|(name, value)| {
+            (
+                name.clone().into(),
+                liquid::model::Value::scalar(value.clone()),
+            )
+        })
+        .collect();
+    for path in files(project)?.into_iter().filter(|path| is_liquid(path)) {
+        let relative = path.strip_prefix(project).unwrap();
+        let rendered = parser
+            .parse(&std::fs::read_to_string(&path)?)
+            .and_then(|template| template.render(&globals))
+            .map_err(|e| {
+                say!("{}: {}", relative.display(), e);
+                Error::TemplateRender
+            })?;
+        let target = path.with_extension("");
+        if target.exists() {
+            say!(
+                "{} would be rendered to {}, which already exists",
+                relative.display(),
+                target.strip_prefix(project).unwrap().display()
+            );
+            return Err(Error::FileNameCollision);
+        }
+        std::fs::write(&target, rendered)?;
+        std::fs::remove_file(&path)?;
+    }
+    Ok(())
+}
+
+/// Replaces the `{{name}}` and `{{ name }}` tokens of known variables in `text`,
+/// in one pass: a value's own tokens are left as they are.
+fn fill(text: &str, vars: &HashMap<String, String>) -> String {
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let value = rest[2..].find("}}").and_then(|end| {
+            let inner = &rest[2..end + 2];
+            let name = match inner.strip_prefix(' ').and_then(|n| n.strip_suffix(' ')) {
+                Some(name) => name,
+                None => inner,
+            };
+            vars.get(name).map(|value| (value, end + 4))
+        });
+        match value {
+            Some((value, len)) => {
+                out.push_str(value);
+                rest = &rest[len..];
+            }
+            None => {
+                out.push_str("{{");
+                rest = &rest[2..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Substitutes the `{{name}}` tokens in the names of the files and
+/// directories of `dir`, like `src/{{module}}.rs`, the contents of a directory
+/// before the directory itself. A name left empty, `.`, `..` or with a
+/// separator in it is an error, as is one taken by another file.
+fn substitute_paths(dir: &Path, vars: &HashMap<String, String>) -> Result<(), Error> {
+    let mut entries = std::fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    entries.sort();
+    for path in entries {
+        if path.is_dir() && !path.is_symlink() {
+            substitute_paths(&path, vars)?;
+        }
+        let name = path.file_name().unwrap().to_string_lossy();
+        if !name.contains("{{") {
+            continue;
+        }
+        let substituted = fill(&name, vars);
+        if substituted == name {
+            continue;
+        }
+        let illegal = substituted.is_empty()
+            || substituted == "."
+            || substituted == ".."
+            || substituted.contains(['/', '\\', '\0']);
+        if illegal {
+            say!(
+                "{} would be named `{}` once substituted",
+                path.display(),
+                substituted
+            );
+            return Err(Error::InvalidFileName);
+        }
+        let target = dir.join(&substituted);
+        if target.symlink_metadata().is_ok() {
+            say!(
+                "{} would be named {}, which already exists",
+                path.display(),
+                target.display()
+            );
+            return Err(Error::FileNameCollision);
+        }
+        std::fs::rename(&path, &target)?;
+    }
+    Ok(())
+}
+
+/// Fails early when the filesystem of the clone or of the destination has
+/// less than `min` MiB available, instead of running out of space halfway.
+fn check_free_space(ctx: &Context, min: u64) -> Result<(), Error> {
+    let clone_fs = ctx.tmp_path.parent().unwrap_or(&ctx.tmp_path);
+    let dest_fs = ctx.staging_path.parent().unwrap_or(&ctx.staging_path);
+    for dir in [clone_fs, dest_fs] {
+        let available = fs2::available_space(dir).map_err(|e| {
+            say!("{}: {}", dir.display(), e);
+            Error::FileSystemFault
+        })? / (1024 * 1024);
+        if available < min {
+            say!(
+                "only {} MiB available on {}, at least {} MiB are required",
+                available,
+                dir.display(),
+                min
+            );
+            return Err(Error::InsufficientSpace);
+        }
+    }
+    Ok(())
+}
+
+/// A template fetched into a scratch directory.
+struct Fetched {
+    /// The template commit that was checked out, `None` for archives.
+    commit: Option<String>,
+    manifest: Manifest,
+    package: String,
+    /// Where the package is, its conditional files already left out.
+    root: PathBuf,
+    /// The template's `.git`, set aside for the project to keep.
+    git: Option<PathBuf>,
+}
+
+/// Clones or extracts `source` into `into` and finds its package there.
+/// `select` applies `--package` and `--template-subpath`, which are about the
+/// template asked for rather than the ones it builds on.
+fn fetch(
+    ctx: &Context,
+    source: &Source,
+    into: &Path,
+    rev: Option<&[String]>,
+    select: bool,
+    timings: &mut Timings,
+    progress: &mut dyn FnMut(ProgressEvent),
+) -> Result<Fetched, Error> {
+    let mut commit = None;
+    let package = match source {
+        Source::Git {
+            url,
+            branch,
+            package,
+        } => {
+            let cache = match ctx.cache {
+                true => cache_entry(url),
+                false => None,
+            };
+            // the bases are checked out whole, the path specs being about the template
+            let paths = match select {
+                true => ctx.checkout_path_spec.as_slice(),
+                false => &[],
+            };
+            // held until the clone from the entry is done; `--rev` doesn't use it
+            let _lock = match (&cache, rev) {
+                (Some(entry), None) => Some(lock_cache(entry, ctx.wait_for_lock)?),
+                _ => None,
+            };
+            let checkout = clone(
+                url,
+                branch,
+                rev,
+                cache.as_deref(),
+                into,
+                progress,
+                ctx.fetch_all_branches,
+                paths,
+                &ctx.network,
+            )?;
+            let repo = Repository::open(into)?;
+            if repo.is_empty().unwrap_or(false) {
+                say!("{} has no commits yet", url);
+                return Err(Error::EmptyTemplate);
+            }
+            if !paths.is_empty() {
+                check_path_specs(&repo, paths)?;
+            }
+            if ctx.checkout_submodules {
+                update_submodules(&repo)?;
+            }
+            let head = repo.head().and_then(|head| head.peel_to_commit())?;
+            if ctx.template_ref_info {
+                say!(
+                    "template commit {} {}",
+                    head.id(),
+                    head.summary().unwrap_or_default()
+                );
+            }
+            let head = head.id();
+            if ctx.verify_signature {
+                verify_signature(&repo, head)?;
+            }
+            commit = Some(head.to_string());
+            timings.lap_split("clone", Some(("checkout", checkout)));
+            package.clone()
+        }
+        Source::Archive(_) | Source::Release(_) if ctx.verify_signature => {
+            say!("an archive has no commit signature to verify");
+            return Err(Error::BadSignature);
+        }
+        Source::Archive(archive) => {
+            let package = extract(archive, into)?;
+            timings.lap("extract");
+            Some(package)
+        }
+        Source::Release(release) => {
+            let archive = into.with_extension("download");
+            let downloaded = download_release(release, &archive, &ctx.network);
+            timings.lap("download");
+            let package = downloaded.and_then(|_| extract(&archive, into));
+            if let Err(e) = std::fs::remove_file(&archive) {
+                if e.kind() != io::ErrorKind::NotFound {
+                    say!("failed to remove {}: {}", archive.display(), e);
+                }
+            }
+            timings.lap("extract");
+            Some(package?)
+        }
+        Source::Local { .. } if ctx.verify_signature => {
+            say!("a local template has no commit signature to verify");
+            return Err(Error::BadSignature);
+        }
+        Source::Local { dir, package } => {
+            copy_local(dir, into)?;
+            timings.lap("copy-template");
+            package.clone()
+        }
+    };
+    if select && !ctx.checkout_path_spec.is_empty() && !matches!(source, Source::Git { .. }) {
+        warn(
+            ctx.strict,
+            "only a git template is checked out by path, using all of it",
+        )?;
+    }
+    let manifest = read_manifest(into)?;
+    let package = match source {
+        Source::Git { .. } => manifest.package.clone().or(package),
+        Source::Archive(_) | Source::Release(_) => package,
+        // the directory is the package unless something says otherwise
+        Source::Local { .. } => manifest.package.clone().or(package).or(Some(String::new())),
+    };
+    let package = match (&ctx.template_subpath, &ctx.package) {
+        (Some(glob), package) if select => Some(find_package(ctx, into, glob, package.as_deref())?),
+        (None, Some(package)) if select => Some(package.clone()),
+        _ => package,
+    }
+    .ok_or_else(|| {
+        say!(
+            "the template names no package, add `package` to its {}",
+            MANIFEST
+        );
+        Error::NoPackage
+    })?;
+    let package = confined(into, package)?;
+    let package = match into.join(&package).is_dir() {
+        true => package,
+        false => confined(into, package_ignoring_case(ctx, into, &package)?)?,
+    };
+
+    let keep_git = select && ctx.keep_git.or(manifest.keep_git).unwrap_or(false);
+    let mut git = None;
+    // a package of `.` or nothing at all is the repository itself, minus
+    // what only matters to git and to this tool
+    let root = if package.is_empty() || Path::new(&package) == Path::new(".") {
+        if keep_git && matches!(source, Source::Git { .. }) {
+            let aside = into.with_extension("git");
+            ctx.scratch.add(aside.clone());
+            std::fs::rename(into.join(".git"), &aside)?;
+            git = Some(aside);
+        } else {
+            cleanup(&[into.join(".git")]);
+        }
+        match std::fs::remove_file(into.join(MANIFEST)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                say!("{}", e);
+                return Err(Error::FileSystemFault);
+            }
+            _ => {}
+        }
+        into.to_path_buf()
+    } else {
+        into.join(&package)
+    };
+    if keep_git && git.is_none() {
+        warn(
+            ctx.strict,
+            "only a template that is a whole git repository can keep its history, leaving it out",
+        )?;
+    }
+    apply_conditionals(&manifest, &ctx.vars, &root)?;
+    Ok(Fetched {
+        commit,
+        manifest,
+        package,
+        root,
+        git,
+    })
+}
+
+/// Fails when one of `paths` matches nothing that `repo` has checked out, a
+/// typo that would otherwise leave the clone empty.
+fn check_path_specs(repo: &Repository, paths: &[String]) -> Result<(), Error> {
+    let tree = repo.head()?.peel_to_tree()?;
+    let mut unmatched = Vec::new();
+    for path in paths {
+        let spec = git2::Pathspec::new([path])?;
+        if spec
+            .match_tree(&tree, git2::PathspecFlags::NO_MATCH_ERROR)
+            .is_err()
+        {
+            unmatched.push(path.as_str());
+        }
+    }
+    if !unmatched.is_empty() {
+        say!(
+            "the template has nothing matching `{}`",
+            unmatched.join("`, `")
+        );
+        return Err(Error::NoSuchPathSpec);
+    }
+    Ok(())
+}
+
+/// `package` if it names a directory inside `into`, which a `..` or an absolute
+/// path in a descriptor, or a symlink in the template, could otherwise take
+/// the copy out of.
+fn confined(into: &Path, package: String) -> Result<String, Error> {
+    let outside = || {
+        say!("the template's package `{}` is outside of it", package);
+        Error::PackageOutsideTemplate
+    };
+    let escapes = Path::new(&package)
+        .components()
+        .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir));
+    if escapes {
+        return Err(outside());
+    }
+    let resolved = into.join(&package);
+    if resolved.exists() {
+        let within = match (resolved.canonicalize(), into.canonicalize()) {
+            (Ok(resolved), Ok(into)) => resolved.starts_with(into),
+            _ => false,
+        };
+        if !within {
+            return Err(outside());
+        }
+    }
+    Ok(package)
+}
+
+/// The directory of the template that is `package` but for the case of its
+/// names, e.g. `Erc20` for `erc20`, as templates written on a case-insensitive
+/// filesystem can have.
+fn package_ignoring_case(ctx: &Context, into: &Path, package: &str) -> Result<String, Error> {
+    let mut found = PathBuf::new();
+    for component in Path::new(package).components() {
+        let Component::Normal(name) = component else {
+            found.push(component);
+            continue;
+        };
+        if into.join(&found).join(name).is_dir() {
+            found.push(name);
+            continue;
+        }
+        let wanted = name.to_string_lossy().to_lowercase();
+        let entries = std::fs::read_dir(into.join(&found))
+            .and_then(|entries| entries.collect::<Result<Vec<_>, _>>())?;
+        let variants: Vec<_> = entries
+            .iter()
+            .filter(|entry| entry.path().is_dir())
+            .map(|entry| entry.file_name())
+            .filter(|variant| variant.to_string_lossy().to_lowercase() == wanted)
+            .collect();
+        match variants.as_slice() {
+            [] => {
+                say!("the template has no `{}` directory", package);
+                return Err(Error::NoPackage);
+            }
+            [variant] => found.push(variant),
+            _ => {
+                let variants: Vec<_> = variants.iter().map(|v| v.to_string_lossy()).collect();
+                say!(
+                    "`{}` matches {} when ignoring case",
+                    package,
+                    variants.join(", ")
+                );
+                return Err(Error::AmbiguousPackage);
+            }
+        }
+    }
+    let found = found.to_string_lossy().into_owned();
+    warn(
+        ctx.strict,
+        &format!("the template has no `{}`, using `{}`", package, found),
+    )?;
+    Ok(found)
+}
+
+/// The directory of the template matching `glob`, `package` saying which one
+/// when there are several.
+fn find_package(
+    ctx: &Context,
+    into: &Path,
+    glob: &GlobMatcher,
+    package: Option<&str>,
+) -> Result<String, Error> {
+    let mut matches = Vec::new();
+    let mut dirs = vec![into.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let entries = std::fs::read_dir(&dir)?;
+        for entry in entries {
+            let path = entry?.path();
+            if !path.is_dir() || path.file_name() == Some(".git".as_ref()) {
+                continue;
+            }
+            let relative = path
+                .strip_prefix(into)
+                .unwrap()
+                .to_string_lossy()
+                .into_owned();
+            if glob.is_match(&relative) {
+                matches.push(relative);
+            }
+            dirs.push(path);
+        }
+    }
+    matches.sort();
+    let found = match (matches.as_slice(), package) {
+        ([], _) => {
+            say!("no directory of the template matches `{}`", glob.glob());
+            return Err(Error::NoPackage);
+        }
+        (_, Some(package)) => matches
+            .iter()
+            .find(|m| Path::new(m) == Path::new(package))
+            .cloned()
+            .ok_or_else(|| {
+                say!("`{}` is not one of {}", package, matches.join(", "));
+                Error::NoPackage
+            })?,
+        ([only], None) => only.clone(),
+        (_, None) => {
+            say!(
+                "`{}` matches {}, pass --package to pick one",
+                glob.glob(),
+                matches.join(", ")
+            );
+            return Err(Error::AmbiguousPackage);
+        }
+    };
+    if ctx.verbose {
+        say!("`{}` resolved to {}", glob.glob(), found);
+    }
+    Ok(found)
+}
+
+/// Fetches the chain of `base` templates `manifest` builds on, and returns
+/// their package roots, the most basic first.
+fn fetch_bases(
+    ctx: &Context,
+    manifest: &Manifest,
+    timings: &mut Timings,
+    progress: &mut dyn FnMut(ProgressEvent),
+) -> Result<Vec<PathBuf>, Error> {
+    let mut chain = vec![ctx.class.clone()];
+    let mut roots = Vec::new();
+    let mut base = manifest.base.clone();
+    while let Some(class) = base {
+        chain.push(class.clone());
+        if chain[..chain.len() - 1].contains(&class) {
+            say!("the templates inherit in a cycle: {}", chain.join(" -> "));
+            return Err(Error::InheritanceCycle);
+        }
+        let source = match lookup_in(&class, ctx.index.as_deref())? {
+            Source::Git {
+                url,
+                branch,
+                package,
+            } => Source::Git {
+                url: match &ctx.mirror {
+                    Some(mirror) => mirrored(&url, mirror, ctx.strict)?,
+                    None => url,
+                },
+                branch,
+                package,
+            },
+            source => source,
+        };
+        let into = random_path();
+        if !ctx.keep_tmp {
+            ctx.scratch.add(into.clone());
+        }
+        let fetched = fetch(ctx, &source, &into, None, false, timings, progress)?;
+        roots.push(fetched.root);
+        base = fetched.manifest.base;
+    }
+    roots.reverse();
+    Ok(roots)
+}
+
+/// The scratch directories of the runs under way, for Ctrl-C or a failure
+/// ending the process to remove.
+static SCRATCH: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+fn remove_scratch() {
+    cleanup(&SCRATCH.lock().unwrap());
+}
+
+/// The scratch directories of one run, removed when it is dropped, and
+/// listed in `SCRATCH` until then. Runs side by side only remove their own.
+#[derive(Debug, Default)]
+struct Scratch(RefCell<Vec<PathBuf>>);
+
+impl Scratch {
+    fn add(&self, path: PathBuf) {
+        SCRATCH.lock().unwrap().push(path.clone());
+        self.0.borrow_mut().push(path);
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let paths = self.0.take();
+        cleanup(&paths);
+        SCRATCH.lock().unwrap().retain(|path| !paths.contains(path));
+    }
+}
+
+/// Clones the template's repository into `dir` as it is, `.git` and all, for
+/// `--clone-only`.
+fn clone_template(ctx: &Context, dir: &Path) -> Result<(), Error> {
+    let Source::Git { url, branch, .. } = &ctx.source else {
+        say!(
+            "`{}` is not a git template, there is nothing to clone",
+            ctx.class
+        );
+        return Err(Error::NoSuchClass);
+    };
+    if dir
+        .read_dir()
+        .is_ok_and(|mut entries| entries.next().is_some())
+    {
+        say!("{} exists and is not empty", dir.display());
+        return Err(Error::InvalidDest);
+    }
+    let cache = match (ctx.cache, &ctx.rev) {
+        (true, None) => cache_entry(url),
+        _ => None,
+    };
+    let _lock = match &cache {
+        Some(entry) => Some(lock_cache(entry, ctx.wait_for_lock)?),
+        None => None,
+    };
+    clone(
+        url,
+        branch,
+        ctx.rev.as_deref(),
+        cache.as_deref(),
+        dir,
+        &mut printer(ctx.progress),
+        ctx.fetch_all_branches,
+        &[],
+        &ctx.network,
+    )?;
+    // a clone of the cache entry, or of `--rev`, doesn't know the template
+    let repo = Repository::open(dir)?;
+    match repo.find_remote("origin") {
+        Ok(_) => repo.remote_set_url("origin", url)?,
+        Err(_) => drop(repo.remote("origin", url)?),
+    }
+    if ctx.checkout_submodules {
+        update_submodules(&repo)?;
+    }
+    say!("cloned {} into {}", url, dir.display());
+    Ok(())
+}
+
+/// Prints the README of the template's package, or of the template when the
+/// package has none, fetched as it would be for a run.
+fn print_readme(ctx: &Context) -> Result<(), Error> {
+    let mut timings = Timings::new();
+    let Fetched { package, root, .. } = fetch(
+        ctx,
+        &ctx.source,
+        &ctx.tmp_path,
+        ctx.rev.as_deref(),
+        true,
+        &mut timings,
+        &mut printer(ctx.progress),
+    )?;
+    let readme = [&root, &ctx.tmp_path]
+        .iter()
+        .flat_map(|dir| ["README.md", "README", "readme.md"].map(|name| dir.join(name)))
+        .find(|path| path.is_file());
+    match readme {
+        Some(readme) => print!("{}", std::fs::read_to_string(readme)?),
+        None => say!("the template has no README for {}", package),
+    }
+    Ok(())
+}
+
+fn run(ctx: &Context, progress: &mut dyn FnMut(ProgressEvent)) -> Result<RunOutcome, Error> {
+    let started = Instant::now();
+    let mut timings = Timings::new();
+    // before anything is fetched, so a refusal is immediate
+    let merge_into = existing_dest(ctx)?;
+    if let Some(min) = ctx.min_free_space {
+        check_free_space(ctx, min)?;
+    }
+    let Fetched {
+        commit,
+        manifest,
+        package,
+        mut root,
+        git,
+    } = fetch(
+        ctx,
+        &ctx.source,
+        &ctx.tmp_path,
+        ctx.rev.as_deref(),
+        true,
+        &mut timings,
+        progress,
+    )
+    .map_err(|e| match (e, &ctx.locked) {
+        (Error::NothingFetched, Some(commit)) => {
+            say!(
+                "{} is locked to {}, which the template no longer has",
+                ctx.class,
+                commit
+            );
+            Error::LockedCommitMissing
+        }
+        (e, _) => e,
+    })?;
+    let bases = fetch_bases(ctx, &manifest, &mut timings, progress)?;
+    if let Some(prefix) = &ctx.strip_prefix {
+        root = root.join(prefix);
+        let nested = Path::new(prefix)
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)));
+        if !nested || !root.is_dir() {
+            say!("`{}` is not a directory inside {}", prefix, package);
+            return Err(Error::NoSuchPrefix);
+        }
+    }
+    let layers: Vec<_> = bases.iter().chain([&root]).collect();
+    if !ctx.allow_empty {
+        let mut empty = true;
+        for layer in &layers {
+            empty &= files(layer)?.is_empty();
+        }
+        if empty {
+            say!(
+                "{} has no files, the template's `package` or --package probably names the \
+                 wrong directory; pass --allow-empty if it is meant to be empty",
+                package
+            );
+            return Err(Error::EmptyPackage);
+        }
+    }
+    let staged = ctx.staging_path.join(root.file_name().unwrap());
+    if ctx.license.is_some() && !ctx.force {
+        let shipped = layers
+            .iter()
+            .find_map(|layer| LICENSE_FILES.iter().find(|name| layer.join(name).exists()));
+        if let Some(name) = shipped {
+            say!(
+                "the template already has a {}, pass --force to replace it",
+                name
+            );
+            return Err(Error::LicenseExists);
+        }
+    }
+
+    if ctx.print_plan {
+        Plan::new(ctx, &manifest, commit.as_deref(), &package).print(ctx.json);
+    }
+
+    say!("{} ->  {}", root.display(), ctx.path.display());
+    std::fs::create_dir_all(&staged)?;
+    // every layer's files go over the ones before it
+    let options = CopyOptions {
+        overwrite: true,
+        content_only: true,
+        ..CopyOptions::new()
+    };
+    for layer in &layers {
+        let handler = |process: fs_extra::dir::TransitProcess| {
+            progress(ProgressEvent::Copy {
+                copied_bytes: process.copied_bytes,
+                total_bytes: process.total_bytes,
+            });
+            fs_extra::dir::TransitProcessResult::ContinueOrAbort
+        };
+        fs_extra::dir::copy_with_progress(layer, &staged, &options, handler).map_err(|e| {
+            say!("{}", e);
+            Error::FileSystemFault
+        })?;
+    }
+    for cruft in find_cruft(&staged)? {
+        let relative = cruft.strip_prefix(&staged).unwrap();
+        if ctx.no_cruft_filter {
+            warn(
+                ctx.strict,
+                &format!("the template has {}, keeping it", relative.display()),
+            )?;
+            continue;
+        }
+        warn(
+            ctx.strict,
+            &format!("the template has {}, leaving it out", relative.display()),
+        )?;
+        let removed = match cruft.is_dir() {
+            true => std::fs::remove_dir_all(&cruft),
+            false => std::fs::remove_file(&cruft),
+        };
+        removed?;
+    }
+    if ctx.minimal {
+        let mut patterns = match &manifest.minimal {
+            Some(patterns) => patterns.clone(),
+            None => MINIMAL.map(String::from).to_vec(),
+        };
+        patterns.extend(ctx.minimal_include.iter().cloned());
+        keep_minimal(&staged, &patterns)?;
+        if !ctx.no_rewrite {
+            drop_missing_targets(&staged)?;
+        }
+    }
+    if ctx.flatten {
+        flatten(&staged, &ctx.scratch)?;
+    }
+    timings.lap("copy");
+
+    if ctx.strict {
+        let unused = unused_vars(&staged, &manifest, &ctx.file_vars)?;
+        if !unused.is_empty() {
+            say!(
+                "the template doesn't use `{}` from --set-from-file",
+                unused.join("`, `")
+            );
+            return Err(Error::UnusedVariable);
+        }
+    }
+
+    let pointers = lfs_pointers(&staged)?;
+    if !pointers.is_empty() {
+        let fetched = match &ctx.source {
+            Source::Git { url, .. } => lfs_smudge(&ctx.tmp_path, url, &pointers, &ctx.network),
+            _ => false,
+        };
+        if !fetched {
+            let names: Vec<_> = pointers
+                .iter()
+                .map(|p| p.strip_prefix(&staged).unwrap().display().to_string())
+                .collect();
+            say!(
+                "{} of the template are Git LFS pointers rather than their content; \
+                 git-lfs (https://git-lfs.com) needs to be installed to fetch it",
+                names.join(", ")
+            );
+            return Err(Error::LfsPointers);
+        }
+    }
+
+    // done while the project is still staged, so that a merge leaves the
+    // files already in the destination alone
+    if !ctx.no_substitute {
+        substitute(
+            &staged,
+            &ctx.vars,
+            ctx.replace_in.as_ref(),
+            ctx.max_file_size,
+            ctx.verbose,
+            ctx.liquid,
+            ctx.marker,
+        )?;
+        if ctx.liquid {
+            render_liquid(&staged, &ctx.vars)?;
+        }
+        // after the contents, which `--replace-in` matches by their template names
+        substitute_paths(&staged, &ctx.vars)?;
+    }
+    let (author, email) = identity(ctx);
+    let set_authors = ctx.author.is_some() || ctx.email.is_some();
+    let authors = toml_edit::Array::from_iter([format!("{} <{}>", author, email)]);
+    // what a member inherits from the workspace is set there instead
+    let rewrite_authors = |package: &mut dyn toml_edit::TableLike, in_workspace: bool| {
+        let inherited = in_workspace && package.get("authors").is_some_and(is_inherited);
+        if !inherited && (set_authors || package.contains_key("authors")) {
+            package.insert("authors", toml_edit::value(authors.clone()));
+        }
+    };
+    let members = workspace_members(&staged)?;
+    let is_workspace = !members.is_empty();
+    let mut old_name = None;
+    if !ctx.no_rewrite {
+        edit_package(&staged, |package| {
+            old_name = package
+                .get("name")
+                .and_then(|name| name.as_str())
+                .map(String::from);
+            package.insert("name", toml_edit::value(&ctx.project_name));
+            rewrite_authors(package, is_workspace);
+        })?;
+        edit_workspace_package(&staged, |package| rewrite_authors(package, false))?;
+        for member in &members {
+            edit_package(&staged.join(member), |package| {
+                rewrite_authors(package, true)
+            })?;
+        }
+    }
+    if ctx.exclude_package_metadata {
+        let workspace = find_workspace(&root, &ctx.tmp_path);
+        edit_manifest(&staged, |doc| make_standalone(doc, workspace.as_ref()))?;
+    }
+    if let Some(old) = old_name.filter(|old| *old != ctx.project_name) {
+        rename_package(&staged, &old, &ctx.project_name, &ctx.rename_scope)?;
+    }
+    if let Some(spdx) = &ctx.license {
+        apply_license(&staged, &members, spdx, !ctx.no_rewrite)?;
+    }
+    if let Err(e) = check_cargo_toml(&staged) {
+        let message = format!(
+            "{} is not valid TOML: {}",
+            ctx.path.join("Cargo.toml").display(),
+            e
+        );
+        warn(ctx.strict, &message).map_err(|_| Error::InvalidCargoToml)?;
+    }
+    timings.lap("post-processing");
+
+    // the project's files that came from the template, relative to it
+    let written: Vec<PathBuf> = if let Some(overwrite) = merge_into {
+        let into = &ctx.path;
+        let report = merge(&staged, into, overwrite, ctx.dry_run)?;
+        let (added, overwrote, skipped) = match ctx.dry_run {
+            true => ("would add", "would overwrite", "would skip"),
+            false => ("added", "overwrote", "skipped"),
+        };
+        report
+            .added
+            .iter()
+            .for_each(|p| say!("{} {}", added, p.display()));
+        for p in &report.overwritten {
+            say!("{} {}", overwrote, p.display());
+            if ctx.dry_run {
+                for line in file_diff(&into.join(p), &staged.join(p)).lines() {
+                    say!("{}", line);
+                }
+            }
+        }
+        report
+            .skipped
+            .iter()
+            .for_each(|p| say!("{} {}, it already exists", skipped, p.display()));
+        cleanup(std::slice::from_ref(&ctx.staging_path));
+        timings.lap("merge");
+        report.added.into_iter().chain(report.overwritten).collect()
+    } else if ctx.dry_run {
+        let listed: Vec<_> = files(&staged)?
+            .iter()
+            .map(|p| p.strip_prefix(&staged).unwrap().to_path_buf())
+            .collect();
+        listed
+            .iter()
+            .for_each(|p| say!("would create {}", p.display()));
+        cleanup(std::slice::from_ref(&ctx.staging_path));
+        listed
+    } else {
+        place(&staged, &ctx.path)?;
+        std::fs::remove_dir(&ctx.staging_path).map_err(|e| {
+            say!("{}", e);
+            Error::FileSystemRemoveDir
+        })?;
+        timings.lap("rename");
+        files(&ctx.path)?
+            .iter()
+            .map(|p| p.strip_prefix(&ctx.path).unwrap().to_path_buf())
+            .collect()
+    };
+
+    //std::fs::remove_dir_all(Path::join(Path::new(&ctx.path), ".git")).map_err(|e| {
+    //    println!("{}", e);
+    //    Error::FileSystemRemoveDir
+    //})?;
+
+    if ctx.dry_run {
+        return Ok(RunOutcome {
+            commit,
+            path: ctx.path.clone(),
+            files: written,
+            elapsed: started.elapsed(),
+            timings,
+        });
+    }
+
+    if let Some(git) = &git {
+        keep_history(ctx, git)?;
+    } else if ctx.rename_git_remote.is_some() || ctx.clear_git_remote {
+        warn(
+            ctx.strict,
+            "the template's history isn't kept, it has no remote to rename or clear",
+        )?;
+    }
+
+    if let (Some(url), Some(commit)) = (&ctx.source_ref_lock, &commit) {
+        let lock = Lock {
+            name: ctx.class.clone(),
+            url: url.clone(),
+            commit: commit.clone(),
+        };
+        write_lock(&ctx.path, lock)?;
+    }
+
+    // before the initial commit, so that it is of the formatted code
+    if ctx.fmt && !cargo(&ctx.path, "fmt")? {
+        warn(ctx.strict, "cargo fmt failed, the project is left as it is")?;
+    }
+    // after it, which would otherwise touch the formatted files
+    if ctx.preserve_timestamps {
+        preserve_timestamps(ctx, &layers, &written)?;
+    }
+
+    let message = match (&ctx.commit_message, &commit) {
+        (Some(message), _) => message.clone(),
+        (None, Some(sha)) => format!("Initial commit from {}@{}", ctx.class, sha),
+        (None, None) => format!("Initial commit from {}", ctx.class),
+    };
+    if let Some(repo) = &ctx.worktree {
+        let branch = ctx.branch_from_rev.as_deref().unwrap_or(&ctx.project_name);
+        add_worktree(
+            repo,
+            &ctx.path,
+            branch,
+            &author,
+            &email,
+            &message,
+            &ctx.scratch,
+        )?;
+        timings.lap("worktree");
+    } else if ctx.git_init {
+        git_init(
+            &ctx.path,
+            ctx.branch_from_rev.as_deref(),
+            &author,
+            &email,
+            &message,
+        )?;
+        timings.lap("git-init");
+    }
+
+    // after it, so that the build's output doesn't end up in the commit
+    if ctx.check && !cargo(&ctx.path, "check")? {
+        say!("cargo check failed for {}", ctx.path.display());
+        return Err(Error::CheckFailed);
+    }
+
+    if let Some(message) = &manifest.post_create_message {
+        say!(
+            "{}",
+            render(message, &ctx.vars).unwrap_or_else(|_| message.clone())
+        );
+    }
+
+    Ok(RunOutcome {
+        commit,
+        path: ctx.path.clone(),
+        files: written,
+        elapsed: started.elapsed(),
+        timings,
+    })
+}
+
+/// Moves the `staged` project to its destination `to`, which may be nested in
+/// directories that don't exist yet.
+fn place(staged: &Path, to: &Path) -> Result<(), Error> {
+    if !staged.is_dir() {
+        say!(
+            "the package was staged at {}, but it is not there anymore",
+            staged.display()
+        );
+        return Err(Error::FileSystemRename);
+    }
+    if let Some(parent) = to.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    move_dir(staged, to).map_err(|e| {
+        say!(
+            "cannot move {} to {}: {}",
+            staged.display(),
+            to.display(),
+            e
+        );
+        Error::FileSystemRename
+    })
+}
+
+/// Makes the template's history, `git`, the project's, pointing `origin` at
+/// the template rather than wherever it was cloned from, or at the url of
+/// `--rename-git-remote`. `--clear-git-remote` removes it instead.
+fn keep_history(ctx: &Context, git: &Path) -> Result<(), Error> {
+    let target = ctx.path.join(".git");
+    if ctx.strip_prefix.is_some() || target.exists() {
+        let why = match ctx.strip_prefix {
+            Some(_) => "--strip-prefix takes a directory of the template",
+            None => "the destination is a git repository already",
+        };
+        return warn(
+            ctx.strict,
+            &format!("{}, leaving the template's history out", why),
+        );
+    }
+    // /tmp may well be another file system
+    move_dir(git, &target).map_err(|e| {
+        say!("{}", e);
+        Error::FileSystemFault
+    })?;
+    let repo = Repository::open(&ctx.path)?;
+    match (&ctx.rename_git_remote, &ctx.source) {
+        _ if ctx.clear_git_remote => match repo.remote_delete("origin") {
+            Err(e) if e.code() != git2::ErrorCode::NotFound => return Err(e.into()),
+            _ => {}
+        },
+        (Some(url), _) | (None, Source::Git { url, .. }) => repo.remote_set_url("origin", url)?,
+        _ => {}
+    }
+    Ok(())
+}
+
+/// What a Git LFS pointer file starts with.
+const LFS_POINTER: &[u8] = b"version https://git-lfs.github.com/spec/v1\n";
+
+/// The files of `dir` that are Git LFS pointers, which is what a clone
+/// without git-lfs has in place of the files the template keeps in LFS.
+fn lfs_pointers(dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    let mut pointers = Vec::new();
+    for path in files(dir)? {
+        let meta = std::fs::symlink_metadata(&path)?;
+        // pointers are a few lines, nothing larger is read
+        if meta.is_file() && meta.len() < 1024 && std::fs::read(&path)?.starts_with(LFS_POINTER) {
+            pointers.push(path);
+        }
+    }
+    Ok(pointers)
+}
+
+/// Replaces the LFS `pointers` with their content, which `git lfs smudge`
+/// downloads from the LFS server of `url`, running in the `clone`. Says
+/// whether all of them were, which they aren't without git-lfs.
+fn lfs_smudge(clone: &Path, url: &str, pointers: &[PathBuf], network: &Network) -> bool {
+    if network_disabled(url, network) {
+        say!(
+            "git lfs would fetch from {}, but --no-network is given",
+            url
+        );
+        return false;
+    }
+    let endpoint = format!("{}.git/info/lfs", url.trim_end_matches(".git"));
+    pointers.iter().all(|pointer| {
+        let smudged = std::fs::File::open(pointer).and_then(|stdin| {
+            let mut git = Command::new("git");
+            git.args(["-c", &format!("lfs.url={}", endpoint)]);
+            if let Some(proxy) = &network.proxy {
+                git.args(["-c", &format!("http.proxy={}", proxy)]);
+            }
+            git.args(["lfs", "smudge"])
+                .current_dir(clone)
+                .stdin(stdin)
+                .stderr(std::process::Stdio::null())
+                .output()
+        });
+        match smudged {
+            Ok(output) if output.status.success() => std::fs::write(pointer, output.stdout).is_ok(),
+            _ => false,
+        }
+    })
+}
+
+/// Runs `cargo <command>` in `project`, the output going straight to the
+/// terminal, and says whether it succeeded.
+fn cargo(project: &Path, command: &str) -> Result<bool, Error> {
+    // set when run as `cargo contemplate`, to the cargo that ran it
+    let cargo = env::var_os("CARGO").unwrap_or_else(|| "cargo".into());
+    say!("running cargo {} in {}", command, project.display());
+    let status = Command::new(cargo)
+        .arg(command)
+        .current_dir(project)
+        .status()?;
+    Ok(status.success())
+}
+
+/// Gives each of the `written` files of the project the modification time of
+/// the template file it came from, in the last of `layers` that has it.
+/// Files the template doesn't have, like generated licenses, keep theirs.
+fn preserve_timestamps(
+    ctx: &Context,
+    layers: &[&PathBuf],
+    written: &[PathBuf],
+) -> Result<(), Error> {
+    let mut sources = HashMap::new();
+    for layer in layers {
+        for source in files(layer)? {
+            let relative = source.strip_prefix(layer).unwrap();
+            sources.insert(project_path(ctx, relative), source.clone());
+        }
+    }
+    for relative in written {
+        let Some(source) = sources.get(relative) else {
+            continue;
+        };
+        std::fs::metadata(source)
+            .and_then(|meta| meta.modified())
+            .and_then(|mtime| {
+                std::fs::File::options()
+                    .write(true)
+                    .open(ctx.path.join(relative))?
+                    .set_modified(mtime)
+            })
+            .map_err(|e| {
+                say!("{}: {}", relative.display(), e);
+                Error::FileSystemFault
+            })?;
+    }
+    Ok(())
+}
+
+/// Where the template file at `relative` ends up in the project, once
+/// `--flatten`, the rendering of `.liquid` files and the substitution of
+/// file names are done with it.
+fn project_path(ctx: &Context, relative: &Path) -> PathBuf {
+    let mut path = match ctx.flatten {
+        true => PathBuf::from(relative.file_name().unwrap()),
+        false => relative.to_path_buf(),
+    };
+    if ctx.no_substitute {
+        return path;
+    }
+    if ctx.liquid && is_liquid(&path) {
+        path = path.with_extension("");
+    }
+    path.iter()
+        .map(|name| match name.to_string_lossy() {
+            name if name.contains("{{") => fill(&name, &ctx.vars).into(),
+            _ => name.to_os_string(),
+        })
+        .collect()
+}
+
+/// What `merge` did with each file of the template, by path relative to the project.
+#[derive(Debug, Default)]
+struct MergeReport {
+    added: Vec<PathBuf>,
+    overwritten: Vec<PathBuf>,
+    skipped: Vec<PathBuf>,
+}
+
+/// Copies the files of `from` into `into` one by one, creating directories
+/// as needed. Files `into` already has are kept, or replaced with `overwrite`.
+/// With `dry_run` nothing is copied, only reported.
+fn merge(from: &Path, into: &Path, overwrite: bool, dry_run: bool) -> Result<MergeReport, Error> {
+    let mut report = MergeReport::default();
+    for path in files(from)? {
+        let relative = path.strip_prefix(from).unwrap().to_path_buf();
+        let target = into.join(&relative);
+        let outcome = match target.exists() {
+            false => &mut report.added,
+            true if overwrite => &mut report.overwritten,
+            true => {
+                report.skipped.push(relative);
+                continue;
+            }
+        };
+        if dry_run {
+            outcome.push(relative);
+            continue;
+        }
+        std::fs::create_dir_all(target.parent().unwrap())
+            .and_then(|_| std::fs::copy(&path, &target))
+            .map_err(|e| {
+                say!("{}: {}", target.display(), e);
+                Error::FileSystemFault
+            })?;
+        outcome.push(relative);
+    }
+    Ok(report)
+}
+
+/// The lines of `old` that `new` drops, as `-`, and the ones it brings, as
+/// `+`, with a `@@` line number where each change starts. Files that aren't
+/// UTF-8 are only said to differ.
+fn file_diff(old: &Path, new: &Path) -> String {
+    let (Ok(old), Ok(new)) = (std::fs::read_to_string(old), std::fs::read_to_string(new)) else {
+        return "  binary files differ\n".to_string();
+    };
+    let old: Vec<_> = old.lines().collect();
+    let new: Vec<_> = new.lines().collect();
+    // lcs[i][j] is the longest common subsequence of old[i..] and new[j..]
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = match old[i] == new[j] {
+                true => lcs[i + 1][j + 1] + 1,
+                false => lcs[i + 1][j].max(lcs[i][j + 1]),
+            };
+        }
+    }
+    let mut diff = String::new();
+    let (mut i, mut j) = (0, 0);
+    let mut in_change = false;
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            in_change = false;
+            i += 1;
+            j += 1;
+            continue;
+        }
+        if !in_change {
+            diff.push_str(&format!("  @@ line {}\n", i + 1));
+            in_change = true;
+        }
+        if j == new.len() || (i < old.len() && lcs[i + 1][j] >= lcs[i][j + 1]) {
+            diff.push_str(&format!("  -{}\n", old[i]));
+            i += 1;
+        } else {
+            diff.push_str(&format!("  +{}\n", new[j]));
+            j += 1;
+        }
+    }
+    diff
+}
+
+/// Removes the clone and staging directories. Paths that are already gone are
+/// skipped, so this is safe to call more than once.
+fn cleanup(paths: &[PathBuf]) {
+    for path in paths {
+        if let Err(e) = std::fs::remove_dir_all(path) {
+            if e.kind() != io::ErrorKind::NotFound {
+                say!("failed to remove {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+/// Renames `from` to `to`, or, when they are on different file systems, as
+/// with a tmpfs `/tmp` in a container, copies it over and removes it. A copy
+/// that fails takes back what it added to `to`, and only that.
+fn move_dir(from: &Path, to: &Path) -> io::Result<()> {
+    match std::fs::rename(from, to) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {}
+        renamed => return renamed,
+    }
+    // what is there already isn't ours to clean up
+    let existing = match to.exists() {
+        true => Some(
+            std::fs::read_dir(to)?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<Result<Vec<_>, _>>()?,
+        ),
+        false => None,
+    };
+    std::fs::create_dir_all(to)?;
+    let options = CopyOptions {
+        content_only: true,
+        ..CopyOptions::new()
+    };
+    if let Err(e) = fs_extra::dir::copy(from, to, &options) {
+        // nothing half copied is left in the destination
+        match existing {
+            Some(existing) => {
+                for path in std::fs::read_dir(to)?.flatten().map(|entry| entry.path()) {
+                    if existing.contains(&path) {
+                        continue;
+                    }
+                    let removed = match path.is_dir() && !path.is_symlink() {
+                        true => std::fs::remove_dir_all(&path),
+                        false => std::fs::remove_file(&path),
+                    };
+                    if let Err(e) = removed {
+                        say!("failed to remove {}: {}", path.display(), e);
+                    }
+                }
+            }
+            None => cleanup(&[to.to_path_buf()]),
+        }
+        return Err(io::Error::other(e));
+    }
+    std::fs::remove_dir_all(from)
+}
+
+const LICENSES: [&str; 3] = ["MIT", "Apache-2.0", "MIT OR Apache-2.0"];
+
+const LICENSE_FILES: [&str; 3] = ["LICENSE", "LICENSE-MIT", "LICENSE-APACHE"];
+
+/// The license files to write for an SPDX expression; the dual license gets
+/// the usual `LICENSE-MIT`/`LICENSE-APACHE` pair.
+fn license_texts(spdx: &str) -> Vec<(&'static str, &'static str)> {
+    const MIT: &str = include_str!("../licenses/MIT");
+    const APACHE: &str = include_str!("../licenses/Apache-2.0");
+    match spdx {
+        "MIT" => vec![("LICENSE", MIT)],
+        "Apache-2.0" => vec![("LICENSE", APACHE)],
+        _ => vec![("LICENSE-MIT", MIT), ("LICENSE-APACHE", APACHE)],
+    }
+}
+
+/// Replaces the project's license files and, with `rewrite`, points
+/// `package.license` at `spdx`.
+fn apply_license(
+    project: &Path,
+    members: &[PathBuf],
+    spdx: &str,
+    rewrite: bool,
+) -> Result<(), Error> {
+    for name in LICENSE_FILES {
+        match std::fs::remove_file(project.join(name)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                say!("{}", e);
+                return Err(Error::FileSystemFault);
+            }
+            _ => {}
+        }
+    }
+    for (name, text) in license_texts(spdx) {
+        std::fs::write(project.join(name), text)?;
+    }
+    if !rewrite {
+        return Ok(());
+    }
+
+    // the members of a workspace can inherit either from it, which then has
+    // the license set instead
+    let set_license = |package: &mut dyn toml_edit::TableLike, in_workspace: bool| {
+        let inherited = ["license", "license-file"]
+            .iter()
+            .any(|key| package.get(key).is_some_and(is_inherited));
+        if in_workspace && inherited {
+            return;
+        }
+        package.remove("license-file");
+        package.insert("license", toml_edit::value(spdx));
+    };
+    let is_workspace = !members.is_empty();
+    edit_package(project, |package| set_license(package, is_workspace))?;
+    edit_workspace_package(project, |package| {
+        if package.contains_key("license") || package.contains_key("license-file") {
+            set_license(package, false);
+        }
+    })?;
+    for member in members {
+        edit_package(&project.join(member), |package| set_license(package, true))?;
+    }
+    Ok(())
+}
+
+/// The member crates of the workspace whose root is `project`, relative to
+/// it, as its `workspace.members` globs and `workspace.exclude` say; none
+/// when it isn't the root of one. The root itself isn't counted.
+fn workspace_members(project: &Path) -> Result<Vec<PathBuf>, Error> {
+    let Ok(doc) = std::fs::read_to_string(project.join("Cargo.toml"))
+        .unwrap_or_default()
+        .parse::<toml_edit::DocumentMut>()
+    else {
+        return Ok(Vec::new());
+    };
+    let Some(workspace) = doc.get("workspace").and_then(|w| w.as_table_like()) else {
+        return Ok(Vec::new());
+    };
+    let globs = |key: &str| -> Result<GlobSet, Error> {
+        let mut globs = GlobSetBuilder::new();
+        let patterns = workspace.get(key).and_then(|p| p.as_array());
+        for pattern in patterns.into_iter().flatten().filter_map(|p| p.as_str()) {
+            let glob = GlobBuilder::new(pattern.trim_end_matches('/'))
+                .literal_separator(true)
+                .build()
+                .map_err(|e| {
+                    say!("Cargo.toml: {}", e);
+                    Error::InvalidGlob
+                })?;
+            globs.add(glob);
+        }
+        globs.build().map_err(|e| {
+            say!("Cargo.toml: {}", e);
+            Error::InvalidGlob
+        })
+    };
+    let (included, excluded) = (globs("members")?, globs("exclude")?);
+    let mut members = Vec::new();
+    let mut dirs = vec![project.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(&dir)? {
+            let path = entry?.path();
+            let name = path.file_name().unwrap();
+            if !path.is_dir() || name == ".git" || name == "target" {
+                continue;
+            }
+            let relative = path.strip_prefix(project).unwrap().to_path_buf();
+            if included.is_match(&relative)
+                && !excluded.is_match(&relative)
+                && path.join("Cargo.toml").is_file()
+            {
+                members.push(relative);
+            }
+            dirs.push(path);
+        }
+    }
+    members.sort();
+    Ok(members)
+}
+
+/// Applies `edit` to the `[workspace.package]` table of the project's
+/// Cargo.toml, what its members can inherit, when there is one.
+fn edit_workspace_package(
+    project: &Path,
+    edit: impl FnOnce(&mut dyn toml_edit::TableLike),
+) -> Result<(), Error> {
+    edit_manifest(project, |doc| {
+        let package = doc
+            .get_mut("workspace")
+            .and_then(|w| w.get_mut("package"))
+            .and_then(|p| p.as_table_like_mut());
+        if let Some(package) = package {
+            edit(package);
+        }
+    })
+}
+
+/// Applies `edit` to the `[package]` table of the project's Cargo.toml, when
+/// there is one. One that doesn't parse is left for `check_cargo_toml` to
+/// report.
+fn edit_package(
+    project: &Path,
+    edit: impl FnOnce(&mut dyn toml_edit::TableLike),
+) -> Result<(), Error> {
+    edit_manifest(project, |doc| {
+        if let Some(package) = doc.get_mut("package").and_then(|p| p.as_table_like_mut()) {
+            edit(package);
+        }
+    })
+}
+
+/// Like `edit_package`, for the whole of the project's Cargo.toml.
+fn edit_manifest(
+    project: &Path,
+    edit: impl FnOnce(&mut toml_edit::DocumentMut),
+) -> Result<(), Error> {
+    let manifest = project.join("Cargo.toml");
+    let content = match std::fs::read_to_string(&manifest) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => {
+            say!("{}", e);
+            return Err(Error::FileSystemFault);
+        }
+    };
+    let Ok(mut doc) = content.parse::<toml_edit::DocumentMut>() else {
+        return Ok(());
+    };
+    edit(&mut doc);
+    std::fs::write(&manifest, doc.to_string())?;
+    Ok(())
+}
+
+/// The Cargo.toml of the workspace the template's package at `root` is part
+/// of, looked for in the directories above it, up to `top`.
+fn find_workspace(root: &Path, top: &Path) -> Option<toml_edit::DocumentMut> {
+    root.ancestors()
+        .skip(1)
+        .take_while(|dir| dir.starts_with(top))
+        .filter_map(|dir| std::fs::read_to_string(dir.join("Cargo.toml")).ok())
+        .filter_map(|content| content.parse::<toml_edit::DocumentMut>().ok())
+        .find(|doc| doc.contains_key("workspace"))
+}
+
+fn is_inherited(item: &toml_edit::Item) -> bool {
+    item.as_table_like()
+        .and_then(|table| table.get("workspace"))
+        .and_then(|workspace| workspace.as_bool())
+        == Some(true)
+}
+
+/// Makes a package taken out of its workspace stand on its own: what it
+/// inherits is copied from `workspace` when it is known and dropped
+/// otherwise, and `workspace` and `publish = false` are removed. A dependency
+/// the workspace doesn't define is kept for the user to fix.
+fn make_standalone(doc: &mut toml_edit::DocumentMut, workspace: Option<&toml_edit::DocumentMut>) {
+    let inherited = |section: &str, key: &str| {
+        workspace
+            .and_then(|ws| ws.get("workspace"))
+            .and_then(|ws| ws.get(section))
+            .and_then(|section| section.get(key))
+            .cloned()
+    };
+    if let Some(package) = doc.get_mut("package").and_then(|p| p.as_table_like_mut()) {
+        package.remove("workspace");
+        if package.get("publish").and_then(|p| p.as_bool()) == Some(false) {
+            package.remove("publish");
+        }
+        let keys: Vec<_> = package
+            .iter()
+            .filter(|(_, item)| is_inherited(item))
+            .map(|(key, _)| key.to_string())
+            .collect();
+        for key in keys {
+            match inherited("package", &key) {
+                Some(value) => package.insert(&key, value),
+                None => package.remove(&key),
+            };
+        }
+    }
+    if doc.get("lints").is_some_and(is_inherited) {
+        match workspace
+            .and_then(|ws| ws.get("workspace"))
+            .and_then(|ws| ws.get("lints"))
+        {
+            Some(lints) => doc.insert("lints", lints.clone()),
+            None => doc.remove("lints"),
+        };
+    }
+    for section in ["dependencies", "dev-dependencies", "build-dependencies"] {
+        let Some(deps) = doc.get_mut(section).and_then(|d| d.as_table_like_mut()) else {
+            continue;
+        };
+        let names: Vec<_> = deps
+            .iter()
+            .filter(|(_, item)| is_inherited(item))
+            .map(|(name, _)| name.to_string())
+            .collect();
+        for name in names {
+            let Some(shared) = inherited("dependencies", &name) else {
+                continue;
+            };
+            let mut merged = match shared {
+                toml_edit::Item::Value(toml_edit::Value::String(version)) => {
+                    let mut table = toml_edit::InlineTable::new();
+                    table.insert("version", toml_edit::Value::String(version));
+                    table
+                }
+                toml_edit::Item::Value(toml_edit::Value::InlineTable(table)) => table,
+                toml_edit::Item::Table(table) => table.into_inline_table(),
+                _ => continue,
+            };
+            // what the package adds to the shared dependency
+            let local = deps.get(&name).and_then(|dep| dep.as_table_like()).unwrap();
+            for (key, item) in local.iter().filter(|(key, _)| *key != "workspace") {
+                let Some(value) = item.as_value() else {
+                    continue;
+                };
+                match (
+                    key,
+                    merged.get_mut("features").and_then(|f| f.as_array_mut()),
+                ) {
+                    ("features", Some(features)) => {
+                        features.extend(value.as_array().into_iter().flatten().cloned());
+                        features.fmt();
+                    }
+                    _ => {
+                        merged.insert(key, value.clone());
+                    }
+                }
+            }
+            merged.fmt();
+            deps.insert(&name, toml_edit::value(merged));
+        }
+    }
+}
+
+/// The files besides Cargo.toml that `--rename-scope` can rename the package in.
+const RENAME_SCOPES: [&str; 3] = ["cargo-lock", "readme", "package-json"];
+
+/// Renames the package from `old` to `new` in the files of `project` that
+/// `scope` names, saying which ones changed.
+fn rename_package(project: &Path, old: &str, new: &str, scope: &[String]) -> Result<(), Error> {
+    for file in scope {
+        let name = match file.as_str() {
+            "cargo-lock" => "Cargo.lock",
+            "readme" => "README.md",
+            _ => "package.json",
+        };
+        let path = project.join(name);
+        let content = match std::fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => {
+                say!("{}", e);
+                return Err(Error::FileSystemFault);
+            }
+        };
+        let renamed = match file.as_str() {
+            "cargo-lock" => rename_in_lockfile(&content, old, new),
+            "readme" => rename_in_headings(&content, old, new),
+            _ => rename_in_package_json(&content, old, new),
+        };
+        if let Some(renamed) = renamed {
+            std::fs::write(&path, renamed)?;
+            say!("renamed {} to {} in {}", old, new, name);
+        }
+    }
+    Ok(())
+}
+
+/// The `[[package]]` entries of a Cargo.lock named `old`.
+fn rename_in_lockfile(content: &str, old: &str, new: &str) -> Option<String> {
+    let mut doc = content.parse::<toml_edit::DocumentMut>().ok()?;
+    let mut renamed = false;
+    for package in doc.get_mut("package")?.as_array_of_tables_mut()?.iter_mut() {
+        if package.get("name").and_then(|name| name.as_str()) == Some(old) {
+            package.insert("name", toml_edit::value(new));
+            renamed = true;
+        }
+    }
+    renamed.then(|| doc.to_string())
+}
+
+/// `old` in the markdown headings, the lines starting with `#`.
+fn rename_in_headings(content: &str, old: &str, new: &str) -> Option<String> {
+    let renamed: String = content
+        .split_inclusive('\n')
+        .map(|line| match line.starts_with('#') {
+            true => line.replace(old, new),
+            false => line.to_string(),
+        })
+        .collect();
+    (renamed != content).then_some(renamed)
+}
+
+/// The top-level `name` of a package.json, edited in place so that the rest
+/// of the file, key order included, stays as it is.
+fn rename_in_package_json(content: &str, old: &str, new: &str) -> Option<String> {
+    let name_of = |json: &str| {
+        serde_json::from_str::<serde_json::Value>(json)
+            .ok()
+            .and_then(|json| json.get("name")?.as_str().map(String::from))
+    };
+    if name_of(content)? != old {
+        return None;
+    }
+    let quoted = format!("\"{}\"", old);
+    content.match_indices("\"name\"").find_map(|(at, key)| {
+        let value = content[at + key.len()..]
+            .trim_start()
+            .strip_prefix(':')?
+            .trim_start();
+        if !value.starts_with(&quoted) {
+            return None;
+        }
+        let start = content.len() - value.len();
+        let renamed = format!(
+            "{}\"{}\"{}",
+            &content[..start],
+            new,
+            &content[start + quoted.len()..]
+        );
+        (name_of(&renamed)? == new).then_some(renamed)
+    })
+}
+
+/// Parses the project's Cargo.toml, if it has one, as a last check that
+/// substitution didn't break it.
+fn check_cargo_toml(project: &Path) -> Result<(), String> {
+    match std::fs::read_to_string(project.join("Cargo.toml")) {
+        Ok(content) => toml::from_str::<toml::Table>(&content)
+            .map(|_| ())
+            .map_err(|e| e.to_string()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Who the project is by: `--author` and `--email`, else git's `user.name`
+/// and `user.email`, else placeholders to fill in.
+fn identity(ctx: &Context) -> (String, String) {
+    let config = git2::Config::open_default().ok();
+    let configured = |key: &str| config.as_ref().and_then(|c| c.get_string(key).ok());
+    let author = ctx
+        .author
+        .clone()
+        .or_else(|| configured("user.name"))
+        .unwrap_or_else(|| "Your Name".to_string());
+    let email = ctx
+        .email
+        .clone()
+        .or_else(|| configured("user.email"))
+        .unwrap_or_else(|| "you@example.com".to_string());
+    (author, email)
+}
+
+/// Makes `project` a worktree of the repository at `repo`, for `--worktree`:
+/// its files are committed there on a new `branch` with no history, which
+/// the worktree then checks out in place of the directory.
+fn add_worktree(
+    repo: &Path,
+    project: &Path,
+    branch: &str,
+    author: &str,
+    email: &str,
+    message: &str,
+    scratch: &Scratch,
+) -> Result<(), Error> {
+    let repo = Repository::open(repo)?;
+    if repo.find_branch(branch, git2::BranchType::Local).is_ok() {
+        say!(
+            "{} already has a branch `{}`",
+            repo.path().display(),
+            branch
+        );
+        return Err(Error::InvalidRev);
+    }
+    let tree = repo.find_tree(write_tree(&repo, project)?)?;
+    let signature = git2::Signature::now(author, email)?;
+    let commit = repo.commit(None, &signature, &signature, message, &tree, &[])?;
+    let branch = repo.branch(branch, &repo.find_commit(commit)?, false)?;
+    // the worktree is checked out where the project is, so it is set aside
+    // until then, everything in it being in the commit
+    let aside = project.with_extension("worktree");
+    std::fs::rename(project, &aside)?;
+    scratch.add(aside.clone());
+    let name = project.file_name().unwrap().to_string_lossy();
+    let mut options = git2::WorktreeAddOptions::new();
+    options.reference(Some(branch.get()));
+    if let Err(e) = repo.worktree(&name, project, Some(&options)) {
+        std::fs::rename(&aside, project)?;
+        return Err(e.into());
+    }
+    std::fs::remove_dir_all(&aside)?;
+    say!(
+        "{} is a worktree of {} on `{}`",
+        project.display(),
+        repo.path().display(),
+        branch.name()?.unwrap_or_default()
+    );
+    Ok(())
+}
+
+/// Writes the files under `dir`, all of them, to the object database of
+/// `repo` as a tree, keeping executables and symlinks for what they are.
+fn write_tree(repo: &Repository, dir: &Path) -> Result<Oid, Error> {
+    let mut builder = repo.treebuilder(None)?;
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let kind = entry.file_type()?;
+        let (oid, mode) = if kind.is_symlink() {
+            let target = std::fs::read_link(&path)?;
+            let blob = repo.blob(target.to_string_lossy().as_bytes())?;
+            (blob, 0o120000)
+        } else if kind.is_dir() {
+            (write_tree(repo, &path)?, 0o040000)
+        } else {
+            (repo.blob_path(&path)?, file_mode(&entry.metadata()?))
+        };
+        builder.insert(entry.file_name(), oid, mode)?;
+    }
+    Ok(builder.write()?)
+}
+
+/// The git mode of a regular file, executable or not.
+#[cfg(unix)]
+fn file_mode(meta: &std::fs::Metadata) -> i32 {
+    use std::os::unix::fs::PermissionsExt;
+    match meta.permissions().mode() & 0o111 {
+        0 => 0o100644,
+        _ => 0o100755,
+    }
+}
+
+#[cfg(not(unix))]
+fn file_mode(_: &std::fs::Metadata) -> i32 {
+    0o100644
+}
+
+/// Makes `project` a git repository with everything in it, `.gitignore`
+/// permitting, in an initial commit on `branch`, or on git's default branch.
+/// A project that already is one, as a merge target may be, is left alone.
+fn git_init(
+    project: &Path,
+    branch: Option<&str>,
+    author: &str,
+    email: &str,
+    message: &str,
+) -> Result<(), Error> {
+    if Repository::open(project).is_ok() {
+        say!("{} is already a git repository", project.display());
+        return Ok(());
+    }
+    let commit = || -> Result<(), git2::Error> {
+        let mut options = git2::RepositoryInitOptions::new();
+        options.initial_head(&branch.map_or_else(initial_branch, str::to_string));
+        let repo = Repository::init_opts(project, &options)?;
+        let mut index = repo.index()?;
+        index.add_all(["*"], git2::IndexAddOption::DEFAULT, None)?;
+        index.write()?;
+        let tree = repo.find_tree(index.write_tree()?)?;
+        let signature = git2::Signature::now(author, email)?;
+        repo.commit(Some("HEAD"), &signature, &signature, message, &tree, &[])?;
+        Ok(())
+    };
+    commit().map_err(|e| {
+        say!("{}", e);
+        Error::GitFault
+    })
+}
+
+/// The branch a new repository starts on: git's `init.defaultBranch`, or
+/// `main` when it isn't set, rather than libgit2's `master`.
+fn initial_branch() -> String {
+    git2::Config::open_default()
+        .and_then(|config| config.get_string("init.defaultBranch"))
+        .unwrap_or_else(|_| "main".to_string())
+}
+
+/// Cargo's rules: ASCII letters, digits, `-` and `_`, not starting with a digit.
+fn is_crate_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// What `--version` prints after the crate version: the git library, which
+/// the transports a clone can use depend on, one `name value` per line.
+fn long_version() -> String {
+    let git = git2::Version::get();
+    let (major, minor, patch) = git.libgit2_version();
+    let features: Vec<_> = [
+        ("https", git.https()),
+        ("ssh", git.ssh()),
+        ("threads", git.threads()),
+        ("vendored-libgit2", git.vendored()),
+    ]
+    .iter()
+    .filter(|(_, enabled)| *enabled)
+    .map(|(feature, _)| *feature)
+    .collect();
+    format!(
+        "{}\ngit2 {}\nlibgit2 {}.{}.{}\nfeatures {}",
+        env!("CARGO_PKG_VERSION"),
+        git.crate_version(),
+        major,
+        minor,
+        patch,
+        features.join(" ")
+    )
+}
+
+/// The command line of `cargo contemplate`, `long_version` being what
+/// `--version` prints.
+fn command(long_version: &str) -> clap::Command<'_> {
+    // the subcommands take their own, the arguments of scaffolding conflicting with them
+    let json = || clap::arg!(--json "Print errors as JSON").action(clap::ArgAction::SetTrue);
+    clap::Command::new("cargo")
+        .bin_name("cargo")
+        .subcommand_required(true)
+        .subcommand(
+            clap::command!("contemplate")
+                .long_version(long_version)
+                .args_conflicts_with_subcommands(true)
+                .subcommand_negates_reqs(true)
+                .subcommand(
+                    clap::Command::new("list")
+                        .about("List the available classes")
+                        .arg(json())
+                        .arg(
+                            clap::arg!(--"output-format" <FORMAT> "How to print the classes")
+                                .required(false)
+                                .value_parser(["table", "json", "plain"])
+                                .default_value("table"),
+                        )
+                        .arg(
+                            clap::arg!(--tag <TAG> "Only list the classes tagged TAG, ignoring case; may be given more than once")
+                                .required(false)
+                                .action(clap::ArgAction::Append),
+                        )
+                        .arg(
+                            clap::arg!(--"tag-match" <MATCH> "Whether a class needs any of the --tag or all of them")
+                                .required(false)
+                                .value_parser(["any", "all"])
+                                .default_value("any"),
+                        ),
+                )
+                .subcommand(
+                    clap::Command::new("which")
+                        .about("Show where a class is defined and what it resolves to")
+                        .arg(json())
+                        .arg(clap::arg!(<CLASS>))
+                        .arg(
+                            clap::arg!(--"output-format" <FORMAT> "How to print the class")
+                                .required(false)
+                                .value_parser(["table", "json", "plain"])
+                                .default_value("table"),
+                        ),
+                )
+                .subcommand(
+                    clap::Command::new("history")
+                        .about("Show what was scaffolded when, if history is enabled")
+                        .arg(json()),
+                )
+                .subcommand(
+                    clap::Command::new("update")
+                        .about("Apply to a project what its template changed since it was made, per its contemplate.lock")
+                        .arg(json())
+                        .arg(
+                            clap::arg!([DIR] "The project to update")
+                                .value_parser(clap::value_parser!(PathBuf))
+                                .default_value("."),
+                        )
+                        .arg(
+                            clap::arg!(--"since-commit" "Apply the template's changes since the commit of contemplate.lock as a patch")
+                                .action(clap::ArgAction::SetTrue)
+                                .required(true),
+                        ),
+                )
+                .subcommand(
+                    clap::Command::new("verify")
+                        .about("Check that a class can be fetched and has a valid package and contemplate.toml, without scaffolding it")
+                        .arg(json())
+                        .arg(clap::arg!(<CLASS>))
+                        .arg(
+                            clap::arg!(--"all-errors" "Report every problem instead of stopping at the first")
+                                .action(clap::ArgAction::SetTrue),
+                        ),
+                )
+                .subcommand(
+                    clap::Command::new("init")
+                        .about("Create the config directory with a starter templates.toml")
+                        .arg(json())
+                        .arg(
+                            clap::arg!(--force "Overwrite an existing templates.toml")
+                                .action(clap::ArgAction::SetTrue),
+                        ),
+                )
+                .arg(clap::arg!(<CLASS>).value_parser(clap::value_parser!(std::string::String)))
+                .arg(
+                    clap::arg!(<DEST>)
+                        .value_parser(clap::value_parser!(std::string::String))
+                        .required(false)
+                        .env("CONTEMPLATE_DEST")
+                        .required_unless_present_any([
+                            "print-url",
+                            "list-branches",
+                            "template-readme",
+                            "clone-only",
+                        ]),
+                )
+                .arg(
+                    clap::arg!(--set <KEY_VALUE> "Define a template variable, e.g. name=foo")
+                        .required(false)
+                        .action(clap::ArgAction::Append)
+                        .value_parser(parse_var),
+                )
+                .arg(
+                    clap::arg!(--"set-from-file" <FILE> "Define the template variables in a flat TOML table, with --set taking precedence")
+                        .required(false),
+                )
+                .arg(
+                    clap::arg!(--"from-local" "Take CLASS to be a template in a local directory, used without git")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    clap::arg!(--index <FILE> "Look CLASS up in this file of templates in local directories, instead of the registry")
+                        .required(false)
+                        .value_parser(clap::value_parser!(PathBuf))
+                        .conflicts_with("from-local"),
+                )
+                .arg(
+                    clap::arg!(--"print-url" "Print the url, branch and package of CLASS and exit")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    clap::arg!(--"clone-only" <DIR> "Clone the template's repository into DIR, .git and all, and exit without scaffolding")
+                        .required(false)
+                        .value_parser(clap::value_parser!(PathBuf))
+                        .conflicts_with_all(&["print-url", "template-readme"]),
+                )
+                .arg(
+                    clap::arg!(--"template-readme" "Print the README of CLASS's package and exit, without scaffolding")
+                        .action(clap::ArgAction::SetTrue)
+                        .conflicts_with("print-url"),
+                )
+                .arg(
+                    clap::arg!(--"list-branches" "Print the branches of CLASS's repository without cloning it, and exit")
+                        .action(clap::ArgAction::SetTrue)
+                        .conflicts_with("print-url"),
+                )
+                .arg(
+                    clap::arg!(--tags "Print the tags too with --list-branches")
+                        .action(clap::ArgAction::SetTrue)
+                        .requires("list-branches"),
+                )
+                .arg(
+                    clap::arg!(--"output-format" <FORMAT> "How to print the branches of --list-branches")
+                        .required(false)
+                        .value_parser(["plain", "json"])
+                        .default_value("plain")
+                        .requires("list-branches"),
+                )
+                .arg(
+                    clap::arg!(--rev <REFSPEC> "Fetch this refspec instead of the template's branch, e.g. refs/pull/42/head")
+                        .required(false),
+                )
+                .arg(
+                    clap::arg!(--"ref-type" <TYPE> "What --rev names, when it isn't a full ref; by default a branch, then a tag, then a commit")
+                        .required(false)
+                        .requires("rev")
+                        .value_parser(["branch", "tag", "commit"]),
+                )
+                .arg(
+                    clap::arg!(--mirror <BASE> "Fetch git templates from this mirror instead of their host")
+                        .required(false)
+                        .env("CONTEMPLATE_MIRROR"),
+                )
+                .arg(
+                    clap::arg!(--proxy <URL> "Fetch templates and releases through this proxy")
+                        .required(false)
+                        .env("CONTEMPLATE_PROXY"),
+                )
+                .arg(
+                    clap::arg!(--package <DIR> "Use this directory of the template as the package, instead of the one it names")
+                        .required(false)
+                        .env("CONTEMPLATE_PACKAGE"),
+                )
+                .arg(
+                    clap::arg!(--"template-subpath" <GLOB> "Use the directory of the template matching GLOB as the package")
+                        .required(false)
+                        .env("CONTEMPLATE_TEMPLATE_SUBPATH"),
+                )
+                .arg(
+                    clap::arg!(--"strip-prefix" <SUBDIR> "Use this directory inside the package as the project root")
+                        .required(false),
+                )
+                .arg(
+                    clap::arg!(--branch <BRANCH> "Clone this branch instead of the template's default")
+                        .required(false)
+                        .env("CONTEMPLATE_BRANCH"),
+                )
+                .arg(
+                    clap::arg!(--license <SPDX> "Write this license and set it in Cargo.toml")
+                        .required(false)
+                        .env("CONTEMPLATE_LICENSE")
+                        .value_parser(LICENSES),
+                )
+                .arg(
+                    clap::arg!(--force "Replace files the template already has, such as its LICENSE")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    clap::arg!(--"checkout-submodules" "Recursively check out the template's git submodules")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    clap::arg!(--"min-free-space" <MIB> "Refuse to start unless the temp and destination filesystems have this much space")
+                        .required(false)
+                        .value_parser(clap::value_parser!(u64)),
+                )
+                .arg(
+                    clap::arg!(--"verify-signature" "Refuse templates whose commit isn't signed by an allowed signer")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    clap::arg!(--"trace-timings" "Print how long each phase took")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    clap::arg!(--prefix <STR> "Prepend this to the crate name, e.g. acme-")
+                        .required(false),
+                )
+                .arg(
+                    clap::arg!(--"prefix-dir" "Prepend --prefix to the directory name as well")
+                        .action(clap::ArgAction::SetTrue)
+                        .requires("prefix"),
+                )
+                .arg(
+                    clap::arg!(--"max-file-size" <MIB> "Copy files larger than this verbatim, without substituting variables")
+                        .required(false)
+                        .default_value("5")
+                        .value_parser(clap::value_parser!(u64)),
+                )
+                .arg(
+                    clap::arg!(-v --verbose "Say more about what is being done")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    clap::arg!(--cache "Keep git templates in a local cache and only fetch what changed")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    clap::arg!(--"replace-in" <GLOB> "Only substitute variables in files matching this, e.g. '*.toml'")
+                        .required(false)
+                        .action(clap::ArgAction::Append),
+                )
+                .arg(
+                    clap::arg!(--"template-ref-info" "Print the sha and subject of the template commit that was checked out")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    clap::arg!(--"dest-exists" <POLICY> "What to do when DEST exists: error, merge the files in keeping its own, overwrite its files, or prompt")
+                        .required(false)
+                        .value_parser(["error", "merge", "overwrite", "prompt"])
+                        .default_value("error")
+                        .conflicts_with("merge"),
+                )
+                .arg(
+                    clap::arg!(--merge "Short for --dest-exists merge")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    clap::arg!(--overwrite "With --merge, short for --dest-exists overwrite")
+                        .action(clap::ArgAction::SetTrue)
+                        .requires("merge"),
+                )
+                .arg(
+                    clap::arg!(--"print-plan" "Print the source, package, substitutions and everything else the run is about to do to stderr, then do it")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    clap::arg!(--json "Print the plan of --print-plan, and errors, as JSON")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    clap::arg!(--"dry-run" "List the files that would be written, and with --merge what would happen to each, without writing them")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    clap::arg!(--"relative-to-repo-root" "Take DEST relative to the root of the enclosing git repository")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    clap::arg!(--"wait-for-lock" <SECONDS> "With --cache, give up after this long when another run is using the template's cache entry")
+                        .required(false)
+                        .value_parser(clap::value_parser!(u64))
+                        .requires("cache"),
+                )
+                .arg(
+                    clap::arg!(--"fetch-all-branches" "Fetch every branch of the template into origin/*, not just the one checked out")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    clap::arg!(--"no-rewrite" "Copy the template's Cargo.toml without setting its name, authors or license")
+                        .action(clap::ArgAction::SetTrue)
+                        .conflicts_with("exclude-package-metadata"),
+                )
+                .arg(
+                    clap::arg!(--"no-substitute" "Leave the {{...}} tokens in the template's files as they are, like --substitution-mode none")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    clap::arg!(--"substitution-mode" <MODE> "Which files to substitute in: all of them, those marked with a first line `# contemplate: template`, or none")
+                        .required(false)
+                        .value_parser(["all", "marker", "none"])
+                        .default_value("all")
+                        .conflicts_with("no-substitute"),
+                )
+                .arg(
+                    clap::arg!(--"exclude-package-metadata" "Copy what Cargo.toml inherits from the template's workspace, and drop publish = false")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    clap::arg!(--"rename-scope" <FILES> "Rename the package in these files too, besides Cargo.toml")
+                        .required(false)
+                        .action(clap::ArgAction::Append)
+                        .value_delimiter(',')
+                        .value_parser(["cargo-lock", "readme", "package-json", "all"]),
+                )
+                .arg(
+                    clap::arg!(--fmt "Run cargo fmt in the project once it is created")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    clap::arg!(--check "Run cargo check in the project once it is created, failing if it doesn't build")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    clap::arg!(--"preserve-timestamps" "Give the project's files the modification times they have in the template")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    clap::arg!(--minimal "Copy only Cargo.toml, src/ and README.md of the package, or what its contemplate.toml says is minimal")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    clap::arg!(--"minimal-include" <GLOB> "Keep files matching GLOB too with --minimal, e.g. build.rs")
+                        .required(false)
+                        .action(clap::ArgAction::Append)
+                        .value_delimiter(',')
+                        .requires("minimal"),
+                )
+                .arg(
+                    clap::arg!(--"no-cruft-filter" "Keep editor and OS files like .DS_Store that the template has, instead of leaving them out")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    clap::arg!(--"no-progress" "Don't print the clone's progress, which is also left out when stdout isn't a terminal")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    clap::arg!(--"progress-interval" <MS> "Update the clone's progress at most every MS milliseconds")
+                        .required(false)
+                        .value_parser(clap::value_parser!(u64))
+                        .default_value("100"),
+                )
+                .arg(
+                    clap::arg!(--strict "Fail on any warning, like a Cargo.toml that doesn't parse after substitution")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    clap::arg!(--"keep-git" "Keep the template's git history in the project, when the template is a whole repository")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    clap::arg!(--"no-keep-git" "Leave the template's git history out, even if the template asks to keep it")
+                        .action(clap::ArgAction::SetTrue)
+                        .conflicts_with("keep-git"),
+                )
+                .arg(
+                    clap::arg!(--"rename-git-remote" <URL> "Point origin at URL rather than at the template when its history is kept")
+                        .required(false)
+                        .conflicts_with("no-keep-git"),
+                )
+                .arg(
+                    clap::arg!(--"clear-git-remote" "Remove origin rather than point it at the template when its history is kept")
+                        .action(clap::ArgAction::SetTrue)
+                        .conflicts_with_all(&["no-keep-git", "rename-git-remote"]),
+                )
+                .arg(
+                    clap::arg!(--"allow-empty" "Scaffold the template's package even if it has no files")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    clap::arg!(--tree "Print the files of the new project as a tree, like `tree` does")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    clap::arg!(--"source-ref-lock" "Write the template's url and commit to contemplate.lock in the project, for --locked")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    clap::arg!(--locked "Use the template commit of contemplate.lock in the current directory")
+                        .action(clap::ArgAction::SetTrue)
+                        .conflicts_with_all(&["rev", "ref-type", "branch"]),
+                )
+                .arg(
+                    clap::arg!(--engine <ENGINE> "How to fill in the template: plain {{name}} tokens, or liquid for its .liquid files")
+                        .required(false)
+                        .value_parser(["plain", "liquid"])
+                        .default_value("plain"),
+                )
+                .arg(
+                    clap::arg!(--"git-init" "Make the project a git repository with an initial commit")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    clap::arg!(--worktree "Make the project a new worktree of the repository the command is run in, on a branch of its own")
+                        .action(clap::ArgAction::SetTrue)
+                        .conflicts_with_all(&["git-init", "keep-git", "merge", "dest-exists"]),
+                )
+                .arg(
+                    clap::arg!(--"checkout-path-spec" <SPEC> "Check out only the paths of the template's repository matching SPEC, e.g. contracts/erc20")
+                        .required(false)
+                        .action(clap::ArgAction::Append)
+                        .conflicts_with_all(&["keep-git", "clone-only"]),
+                )
+                .arg(
+                    clap::arg!(--flatten "Copy every file of the template's package to the top of the project, leaving its directories out")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    clap::arg!(--"no-git-init" "Don't make the project a git repository, whatever config.toml says")
+                        .action(clap::ArgAction::SetTrue)
+                        .conflicts_with("git-init"),
+                )
+                .arg(
+                    clap::arg!(--"commit-message" <MESSAGE> "Message of the --git-init commit, instead of one naming the template and its commit")
+                        .required(false)
+                        .requires("git-init"),
+                )
+                .arg(
+                    clap::arg!(--"branch-from-rev" [BRANCH] "Put the --git-init commit on this branch, main if none is given")
+                        .default_missing_value("main")
+                        .requires("git-init"),
+                )
+                .arg(
+                    clap::arg!(--author <NAME> "Author for Cargo.toml and the initial commit, instead of git's user.name")
+                        .required(false),
+                )
+                .arg(
+                    clap::arg!(--email <EMAIL> "Email for Cargo.toml and the initial commit, instead of git's user.email")
+                        .required(false),
+                )
+                .arg(
+                    clap::arg!(--"keep-tmp" "Keep the temporary clone of the template and print where it is")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    clap::arg!(--"no-network" "Fail instead of fetching anything from the network, a template missing from --cache included")
+                        .action(clap::ArgAction::SetTrue),
+                ),
+        )
+}
+
+/// What [`scaffold`] does differently than `cargo contemplate <CLASS> <DEST>`
+/// with no flags, the environment and `config.toml` applying as they do to
+/// the command.
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// `--set`, `project_name` included.
+    pub vars: Vec<(String, String)>,
+    /// `--branch`
+    pub branch: Option<String>,
+    /// `--rev`
+    pub rev: Option<String>,
+    /// `--package`
+    pub package: Option<String>,
+    /// `--git-init`
+    pub git_init: bool,
+    /// `--cache`
+    pub cache: bool,
+    /// `--no-network`
+    pub no_network: bool,
+    /// `--proxy`
+    pub proxy: Option<String>,
+}
+
+/// The project a [`scaffold`] created.
+#[derive(Debug)]
+pub struct Outcome {
+    /// Where it is, absolute.
+    pub path: PathBuf,
+    /// Its files that came from the template, relative to `path`.
+    pub files: Vec<PathBuf>,
+    /// The template's commit, `None` for archives and local templates.
+    pub commit: Option<String>,
+    /// What the run said on the way, the lines the command prints.
+    pub messages: Vec<String>,
+}
+
+/// Why a [`scaffold`] failed, and what the run said up to then, the last
+/// lines being about what went wrong.
+#[derive(Debug)]
+pub struct Failure {
+    pub error: Error,
+    pub messages: Vec<String>,
+}
+
+impl std::fmt::Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.messages.last() {
+            Some(detail) => write!(f, "{}: {}", self.error, detail),
+            None => write!(f, "{}", self.error),
+        }
+    }
+}
+
+impl std::error::Error for Failure {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// Scaffolds `class` into `dest` with `options`. The progress goes to
+/// `on_progress`, or is drawn as the command draws it without one; nothing
+/// else is printed, what the run says is in its [`Outcome`] or [`Failure`].
+pub fn scaffold(
+    class: &str,
+    dest: &Path,
+    options: &Options,
+    on_progress: Option<&mut dyn FnMut(ProgressEvent)>,
+) -> Result<Outcome, Failure> {
+    let previous = SAID.with(|said| said.replace(Some(Vec::new())));
+    let result = scaffold_said(class, dest, options, on_progress);
+    let messages = SAID.with(|said| said.replace(previous)).unwrap_or_default();
+    match result {
+        Ok(outcome) => Ok(Outcome {
+            path: outcome.path,
+            files: outcome.files,
+            commit: outcome.commit,
+            messages,
+        }),
+        Err(error) => Err(Failure { error, messages }),
+    }
+}
+
+/// [`scaffold`], with what it says collected by the caller.
+fn scaffold_said(
+    class: &str,
+    dest: &Path,
+    options: &Options,
+    on_progress: Option<&mut dyn FnMut(ProgressEvent)>,
+) -> Result<RunOutcome, Error> {
+    // what the command would make of no flags, DEST aside
+    let long_version = long_version();
+    let matches = command(&long_version)
+        .try_get_matches_from(["cargo", "contemplate", "--", class, "."])
+        .map_err(|e| {
+            say!("{}", e);
+            Error::InvalidFlags
+        })?;
+    let mut args = args_from(matches.subcommand_matches("contemplate").unwrap())?;
+    args.dest = dest.to_path_buf();
+    args.vars.extend(options.vars.iter().cloned());
+    args.branch = options.branch.clone().or(args.branch);
+    args.rev = options.rev.clone().or(args.rev);
+    args.package = options.package.clone().or(args.package);
+    args.git_init |= options.git_init;
+    args.cache |= options.cache;
+    args.no_network |= options.no_network;
+    args.proxy = options.proxy.clone().or(args.proxy);
+    let context = Context::try_from(args)?;
+    let mut builtin = printer(context.progress);
+    run(&context, on_progress.unwrap_or(&mut builtin))
+}
+
+/// The arguments of a run, from the `contemplate` subcommand's `matches`,
+/// with `config.toml` filling in what they leave out.
+fn args_from(matches: &clap::ArgMatches) -> Result<Args, Error> {
+    let json = matches.get_flag("json");
+    let class = matches.get_one::<String>("CLASS").unwrap().clone();
+    let template_readme = matches.get_flag("template-readme");
+    let clone_only = matches.get_one::<PathBuf>("clone-only").cloned();
+    let allow_empty = matches.get_flag("allow-empty");
+    let tree = matches.get_flag("tree");
+    let liquid = matches.get_one::<String>("engine").unwrap() == "liquid";
+    let source_ref_lock = matches.get_flag("source-ref-lock");
+    let locked = matches.get_flag("locked");
+    let worktree = matches.get_flag("worktree");
+    let flatten = matches.get_flag("flatten");
+    let rename_git_remote = matches.get_one::<String>("rename-git-remote").cloned();
+    let clear_git_remote = matches.get_flag("clear-git-remote");
+    let checkout_path_spec = matches
+        .get_many::<String>("checkout-path-spec")
+        .map(|specs| specs.cloned().collect())
+        .unwrap_or_default();
+    let url_only = matches.get_flag("print-url");
+    let dest = match template_readme || clone_only.is_some() || url_only {
+        // nothing is written, it only has to be a valid destination
+        true => PathBuf::from("readme"),
+        false => PathBuf::from(expand(matches.get_one::<String>("DEST").unwrap())?),
+    };
+
+    let vars = matches
+        .get_many::<(String, String)>("set")
+        .map(|vars| vars.cloned().collect())
+        .unwrap_or_default();
+
+    // config.toml only fills in what neither a flag nor the environment set
+    let defaults = read_config()?.defaults;
+    let keep_git = match (
+        matches.get_flag("keep-git"),
+        matches.get_flag("no-keep-git"),
+    ) {
+        (true, _) => Some(true),
+        (_, true) => Some(false),
+        // the default mustn't make --keep-git or --no-keep-git conflict
+        _ => defaults.keep_git.filter(|&keep| match keep {
+            true => {
+                !matches.get_flag("worktree")
+                    && matches.get_many::<String>("checkout-path-spec").is_none()
+            }
+            false => {
+                !matches.get_flag("clear-git-remote")
+                    && matches.get_one::<String>("rename-git-remote").is_none()
+            }
+        }),
+    };
+
+    let rev = matches.get_one::<String>("rev").cloned();
+    let mirror = matches
+        .get_one::<String>("mirror")
+        .cloned()
+        .or(defaults.mirror);
+    let strip_prefix = matches.get_one::<String>("strip-prefix").cloned();
+    let package = matches.get_one::<String>("package").cloned();
+    let template_subpath = matches.get_one::<String>("template-subpath").cloned();
+    let vars_file = matches.get_one::<String>("set-from-file").cloned();
+    let commit_message = matches.get_one::<String>("commit-message").cloned();
+    let branch_from_rev = matches
+        .get_one::<String>("branch-from-rev")
+        .cloned()
+        .or(defaults.default_branch);
+    let from_local = matches.get_flag("from-local");
+    let index = matches.get_one::<PathBuf>("index").cloned();
+    let exclude_package_metadata = matches.get_flag("exclude-package-metadata");
+    let wait_for_lock = matches.get_one::<u64>("wait-for-lock").copied();
+    let no_rewrite = matches.get_flag("no-rewrite");
+    let substitution_mode = matches.get_one::<String>("substitution-mode").unwrap();
+    let no_substitute = matches.get_flag("no-substitute") || substitution_mode == "none";
+    let marker = substitution_mode == "marker";
+    let minimal = matches.get_flag("minimal");
+    let print_plan = matches.get_flag("print-plan");
+    let minimal_include = matches
+        .get_many::<String>("minimal-include")
+        .map(|globs| globs.cloned().collect())
+        .unwrap_or_default();
+    let preserve_timestamps = matches.get_flag("preserve-timestamps");
+    let fmt = matches.get_flag("fmt");
+    let check = matches.get_flag("check");
+    let rename_scope = matches
+        .get_many::<String>("rename-scope")
+        .map(|scope| scope.cloned().collect())
+        .unwrap_or_default();
+    let branch = matches.get_one::<String>("branch").cloned();
+    let license = matches
+        .get_one::<String>("license")
+        .cloned()
+        .or(defaults.license);
+    let force = matches.get_flag("force");
+    let checkout_submodules = matches.get_flag("checkout-submodules");
+    let min_free_space = matches.get_one::<u64>("min-free-space").copied();
+    let verify_signature = matches.get_flag("verify-signature");
+    let trace_timings = matches.get_flag("trace-timings");
+    let prefix = matches.get_one::<String>("prefix").cloned();
+    let prefix_dir = matches.get_flag("prefix-dir");
+    let max_file_size = *matches.get_one::<u64>("max-file-size").unwrap();
+    let verbose = matches.get_flag("verbose");
+    let cache = matches.get_flag("cache");
+    let replace_in = matches
+        .get_many::<String>("replace-in")
+        .map(|globs| globs.cloned().collect())
+        .unwrap_or_default();
+    let template_ref_info = matches.get_flag("template-ref-info");
+    let dest_exists = match (
+        matches.get_flag("merge"),
+        matches.get_flag("overwrite"),
+        matches.get_one::<String>("dest-exists").unwrap().as_str(),
+    ) {
+        (true, true, _) | (_, _, "overwrite") => DestExists::Overwrite,
+        (true, false, _) | (_, _, "merge") => DestExists::Merge,
+        (_, _, "prompt") => DestExists::Prompt,
+        _ => DestExists::Error,
+    };
+    let keep_tmp = matches.get_flag("keep-tmp");
+    let ref_type = matches.get_one::<String>("ref-type").cloned();
+    let relative_to_repo_root = matches.get_flag("relative-to-repo-root");
+    let no_progress = matches.get_flag("no-progress");
+    let progress_interval = *matches.get_one::<u64>("progress-interval").unwrap();
+    let strict = matches.get_flag("strict");
+    let git_init = match (
+        matches.get_flag("git-init"),
+        matches.get_flag("no-git-init"),
+    ) {
+        (true, _) => true,
+        (_, true) => false,
+        _ => defaults.git_init.unwrap_or(false),
+    };
+    let dry_run = matches.get_flag("dry-run");
+    let fetch_all_branches = matches.get_flag("fetch-all-branches");
+    let no_cruft_filter = matches.get_flag("no-cruft-filter");
+    let author = matches
+        .get_one::<String>("author")
+        .cloned()
+        .or(defaults.author);
+    let email = matches
+        .get_one::<String>("email")
+        .cloned()
+        .or(defaults.email);
+    let no_network = matches.get_flag("no-network");
+    let proxy = matches.get_one::<String>("proxy").cloned();
+
+    Ok(Args {
+        class,
+        dest,
+        vars,
+        rev,
+        mirror,
+        strip_prefix,
+        branch,
+        license,
+        force,
+        checkout_submodules,
+        min_free_space,
+        verify_signature,
+        trace_timings,
+        prefix,
+        prefix_dir,
+        max_file_size,
+        verbose,
+        cache,
+        replace_in,
+        template_ref_info,
+        dest_exists,
+        keep_tmp,
+        ref_type,
+        relative_to_repo_root,
+        no_progress,
+        progress_interval,
+        strict,
+        author,
+        email,
+        git_init,
+        dry_run,
+        fetch_all_branches,
+        no_cruft_filter,
+        package,
+        template_subpath,
+        vars_file,
+        commit_message,
+        preserve_timestamps,
+        rename_scope,
+        branch_from_rev,
+        from_local,
+        index,
+        exclude_package_metadata,
+        wait_for_lock,
+        no_rewrite,
+        no_substitute,
+        marker,
+        minimal,
+        minimal_include,
+        print_plan,
+        json,
+        fmt,
+        check,
+        template_readme,
+        keep_git,
+        clone_only,
+        allow_empty,
+        tree,
+        liquid,
+        source_ref_lock,
+        locked,
+        worktree,
+        flatten,
+        checkout_path_spec,
+        rename_git_remote,
+        clear_git_remote,
+        no_network,
+        proxy,
+    })
+}
+
+/// Runs `cargo contemplate` on the arguments of the process, and exits with
+/// the code of the error it ends on, if any. For the binary, not part of the
+/// library.
+#[doc(hidden)]
+pub fn main() {
+    let long_version = long_version();
+    let mut cmd = command(&long_version);
+    let matches = cmd.get_matches_mut();
+    let matches = match matches.subcommand() {
+        Some(("contemplate", matches)) => matches,
+        _ => unreachable!("clap should ensure we don't get here"),
+    };
+    // checked here rather than by clap, as a DEST from CONTEMPLATE_DEST
+    // would conflict too
+    let dest_given = matches.value_source("DEST") == Some(clap::ValueSource::CommandLine);
+    for flag in ["clone-only", "template-readme"] {
+        if dest_given && matches.value_source(flag) == Some(clap::ValueSource::CommandLine) {
+            cmd.find_subcommand_mut("contemplate")
+                .unwrap()
+                .error(
+                    clap::ErrorKind::ArgumentConflict,
+                    format!("--{} cannot be used with DEST", flag),
+                )
+                .exit();
+        }
+    }
+    // for the subcommands, a run has them in its context
+    let network = Network::new(
+        matches.get_flag("no-network"),
+        matches.get_one::<String>("proxy").cloned(),
+    )
+    .unwrap_or_else(|e| fail(e, matches.get_flag("json")));
+    if let Some(("list", matches)) = matches.subcommand() {
+        let tags: Vec<_> = matches
+            .get_many::<String>("tag")
+            .map(|tags| tags.cloned().collect())
+            .unwrap_or_default();
+        list(
+            matches.get_one::<String>("output-format").unwrap(),
+            &tags,
+            matches.get_one::<String>("tag-match").unwrap() == "all",
+        )
+        .unwrap_or_else(|e| fail(e, matches.get_flag("json")));
+        return;
+    }
+    if let Some(("init", matches)) = matches.subcommand() {
+        init(matches.get_flag("force")).unwrap_or_else(|e| fail(e, matches.get_flag("json")));
+        return;
+    }
+    if let Some(("which", matches)) = matches.subcommand() {
+        which(
+            matches.get_one::<String>("CLASS").unwrap(),
+            matches.get_one::<String>("output-format").unwrap(),
+        )
+        .unwrap_or_else(|e| fail(e, matches.get_flag("json")));
+        return;
+    }
+    if let Some(("history", matches)) = matches.subcommand() {
+        history().unwrap_or_else(|e| fail(e, matches.get_flag("json")));
+        return;
+    }
+    if let Some(("update", matches)) = matches.subcommand() {
+        update(matches.get_one::<PathBuf>("DIR").unwrap(), &network)
+            .unwrap_or_else(|e| fail(e, matches.get_flag("json")));
+        return;
+    }
+    if let Some(("verify", matches)) = matches.subcommand() {
+        verify(
+            matches.get_one::<String>("CLASS").unwrap(),
+            matches.get_flag("all-errors"),
+            &network,
+        )
+        .unwrap_or_else(|e| fail(e, matches.get_flag("json")));
+        return;
+    }
+    let json = matches.get_flag("json");
+
+    let class = matches
+        .get_one::<String>("CLASS")
+        .map(|s| s.as_str())
+        .unwrap()
+        .to_string();
+    if matches.get_flag("list-branches") {
+        list_branches(
+            &class,
+            matches.get_flag("tags"),
+            matches.get_one::<String>("output-format").unwrap(),
+            &network,
+        )
+        .unwrap_or_else(|e| fail(e, json));
+        return;
+    }
+    let args = args_from(matches).unwrap_or_else(|e| fail(e, json));
+    if matches.get_flag("print-url") {
+        print_url(&args).unwrap_or_else(|e| fail(e, json));
+        return;
+    }
+    let context = Context::try_from(args).unwrap_or_else(|e| fail(e, json));
+
+    ctrlc::set_handler(move || {
+        // a fetch is stopped from its progress callback instead, and `run`
+        // returns as usual
+        if FETCHING.load(Ordering::SeqCst) && !CANCELLED.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        remove_scratch();
+        std::process::exit(130);
+    })
+    .expect("failed to install the Ctrl-C handler");
+
+    if let Some(dir) = &context.clone_only {
+        clone_template(&context, dir).unwrap_or_else(|e| fail(e, json));
+        return;
+    }
+
+    if context.template_readme {
+        print_readme(&context).unwrap_or_else(|e| fail(e, json));
+        return;
+    }
+
+    let result = run(&context, &mut printer(context.progress));
+    if context.keep_tmp {
+        println!("kept the template clone in {}", context.tmp_path.display());
+    }
+    let outcome = result.unwrap_or_else(|e| fail(e, json));
+    if context.dry_run {
+        println!(
+            "dry run, nothing was written to {} ({} files)",
+            outcome.path.display(),
+            outcome.files.len()
+        );
+        return;
+    }
+    if let Err(e) = record_history(&class, &outcome.path) {
+        println!("could not record the history: {:?}", e);
+    }
+    println!(
+        "created {} ({} files{}) in {:.1?}",
+        outcome.path.display(),
+        outcome.files.len(),
+        outcome
+            .commit
+            .map(|commit| format!(" from {}", &commit[..7]))
+            .unwrap_or_default(),
+        outcome.elapsed
+    );
+    // the tree is for people, --json is for what reads the output
+    if context.tree && !json {
+        match file_tree(&outcome.path) {
+            Ok(tree) => print!("{}", tree),
+            Err(e) => println!("could not list the project: {:?}", e),
+        }
+    }
+    if context.trace_timings {
+        println!("timings:");
+        for (phase, duration) in &outcome.timings.phases {
+            println!("  {:16} {:>10.1?}", phase, duration);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// What `f` returns, and the lines it said.
+    fn said<T>(f: impl FnOnce() -> T) -> (T, Vec<String>) {
+        SAID.with(|said| said.replace(Some(Vec::new())));
+        let result = f();
+        (result, SAID.with(|said| said.take()).unwrap())
+    }
+
+    fn vars(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn fills_in_known_variables() {
+        let vars = vars(&[("name", "demo"), ("crate", "demo_rs")]);
+        assert_eq!(
+            fill("{{name}} is {{ crate }}, not {{other}}", &vars),
+            "demo is demo_rs, not {{other}}"
+        );
+        // only `{{name}}` and `{{ name }}` are tokens
+        assert_eq!(
+            fill("{{ name}} {{name }} {{  name  }} {name}", &vars),
+            "{{ name}} {{name }} {{  name  }} {name}"
+        );
+        assert_eq!(fill("{{{{name}}}} }}{{", &vars), "{{demo}} }}{{");
+    }
+
+    #[test]
+    fn fills_in_values_as_they_are() {
+        // whatever order the variables come in
+        for _ in 0..16 {
+            let vars = vars(&[("a", "{{b}}"), ("b", "{{c}}"), ("c", "x"), ("d", "{{a}}")]);
+            assert_eq!(fill("{{a}} {{b}} {{ d }}", &vars), "{{b}} {{c}} {{a}}");
+        }
+    }
+
+    #[test]
+    fn dates_the_history_in_utc() {
+        assert_eq!(utc_date(0), "1970-01-01 00:00");
+        assert_eq!(
+            utc_date(951_782_400 + 23 * 3600 + 59 * 60),
+            "2000-02-29 23:59"
+        );
+        assert_eq!(utc_date(1_798_761_600), "2027-01-01 00:00");
+    }
+
+    #[test]
+    fn tries_a_plain_rev_as_a_branch_then_a_tag_then_a_commit() {
+        let sha = "0123456789abcdef0123456789abcdef01234567";
+        assert_eq!(
+            rev_candidates("v1", None).unwrap(),
+            ["refs/heads/v1", "refs/tags/v1"]
+        );
+        assert_eq!(
+            rev_candidates(sha, None).unwrap(),
+            [
+                format!("refs/heads/{}", sha),
+                format!("refs/tags/{}", sha),
+                sha.to_string()
+            ]
+        );
+        // a full ref or refspec is taken as it is
+        for rev in ["refs/pull/42/head", "refs/custom/a:refs/custom/b"] {
+            assert_eq!(rev_candidates(rev, None).unwrap(), [rev]);
+        }
+        assert_eq!(
+            rev_candidates("v1", Some("branch")).unwrap(),
+            ["refs/heads/v1"]
+        );
+        assert_eq!(rev_candidates("v1", Some("tag")).unwrap(), ["refs/tags/v1"]);
+        assert_eq!(rev_candidates(sha, Some("commit")).unwrap(), [sha]);
+        let (result, said) = said(|| rev_candidates(&sha[..7], Some("commit")));
+        assert_eq!(result.unwrap_err().code(), 32);
+        assert_eq!(said, ["`0123456` is not a full commit sha"]);
+    }
+
+    #[test]
+    fn tells_crate_names() {
+        for name in ["demo", "acme-token", "_private", "v2_api", "A"] {
+            assert!(is_crate_name(name), "{}", name);
+        }
+        for name in ["", "2fa", "-demo", "my app", "démo", "a.b", "a/b"] {
+            assert!(!is_crate_name(name), "{}", name);
+        }
+    }
+
+    #[test]
+    fn diffs_the_lines_of_two_files() {
+        let root = tempfile::tempdir().unwrap();
+        let (old, new) = (root.path().join("old"), root.path().join("new"));
+        std::fs::write(&old, "a\nb\nc\nd\n").unwrap();
+        std::fs::write(&new, "a\nB\nc\nd\ne\n").unwrap();
+        assert_eq!(
+            file_diff(&old, &new),
+            "  @@ line 2\n  -b\n  +B\n  @@ line 5\n  +e\n"
+        );
+        assert_eq!(file_diff(&old, &old), "");
+
+        std::fs::write(&new, "").unwrap();
+        assert_eq!(
+            file_diff(&old, &new),
+            "  @@ line 1\n  -a\n  -b\n  -c\n  -d\n"
+        );
+        std::fs::write(&new, [0xff, 0xfe]).unwrap();
+        assert_eq!(file_diff(&old, &new), "  binary files differ\n");
+    }
+
+    #[test]
+    fn renames_the_package_in_the_lockfile() {
+        let lock = "version = 3\n\n[[package]]\nname = \"pkg\"\nversion = \"0.1.0\"\n\n\
+                    [[package]]\nname = \"pkg-core\"\nversion = \"0.1.0\"\n";
+        assert_eq!(
+            rename_in_lockfile(lock, "pkg", "demo").unwrap(),
+            lock.replacen("\"pkg\"", "\"demo\"", 1)
+        );
+        assert_eq!(rename_in_lockfile(lock, "other", "demo"), None);
+        assert_eq!(rename_in_lockfile("version = 3\n", "pkg", "demo"), None);
+        assert_eq!(rename_in_lockfile("[[package]\n", "pkg", "demo"), None);
+    }
+
+    #[test]
+    fn renames_the_package_in_the_headings_only() {
+        let readme = "# pkg\n\nUse pkg like this:\n\n## pkg and friends";
+        assert_eq!(
+            rename_in_headings(readme, "pkg", "demo").unwrap(),
+            "# demo\n\nUse pkg like this:\n\n## demo and friends"
+        );
+        assert_eq!(rename_in_headings("pkg\n", "pkg", "demo"), None);
+    }
+
+    #[test]
+    fn renames_the_package_in_package_json() {
+        let json = "{\n  \"dependencies\": { \"name\": \"pkg\" },\n  \"name\" : \"pkg\",\n  \"version\": \"1.0.0\"\n}\n";
+        assert_eq!(
+            rename_in_package_json(json, "pkg", "demo").unwrap(),
+            "{\n  \"dependencies\": { \"name\": \"pkg\" },\n  \"name\" : \"demo\",\n  \"version\": \"1.0.0\"\n}\n"
+        );
+        assert_eq!(rename_in_package_json(json, "other", "demo"), None);
+        assert_eq!(rename_in_package_json("{\"name\": ", "pkg", "demo"), None);
+        assert_eq!(rename_in_package_json("[]", "pkg", "demo"), None);
+    }
+
+    #[test]
+    fn copies_what_a_package_inherits_from_its_workspace() {
+        let workspace: toml_edit::DocumentMut = "[workspace]\n\
+             lints.rust.unsafe_code = \"forbid\"\n\
+             [workspace.package]\nversion = \"1.2.3\"\n\
+             [workspace.dependencies]\nserde = { version = \"1\", features = [\"std\"] }\n\
+             log = \"0.4\"\n"
+            .parse()
+            .unwrap();
+        let package = "[package]\nname = \"pkg\"\nversion.workspace = true\n\
+             license.workspace = true\nworkspace = \"..\"\npublish = false\n\
+             [lints]\nworkspace = true\n\
+             [dependencies]\nserde = { workspace = true, features = [\"derive\"] }\n\
+             log.workspace = true\nrand = { workspace = true }\n";
+        let mut doc: toml_edit::DocumentMut = package.parse().unwrap();
+        make_standalone(&mut doc, Some(&workspace));
+        let doc: toml::Table = doc.to_string().parse().unwrap();
+        let expected: toml::Table = "[package]\nname = \"pkg\"\nversion = \"1.2.3\"\n\
+             [lints.rust]\nunsafe_code = \"forbid\"\n\
+             [dependencies]\nserde = { version = \"1\", features = [\"std\", \"derive\"] }\n\
+             log = { version = \"0.4\" }\nrand = { workspace = true }\n"
+            .parse()
+            .unwrap();
+        assert_eq!(doc, expected);
+
+        // with no workspace to copy from, what is inherited is dropped
+        let mut doc: toml_edit::DocumentMut = package.parse().unwrap();
+        make_standalone(&mut doc, None);
+        let doc: toml::Table = doc.to_string().parse().unwrap();
+        let expected: toml::Table = "[package]\nname = \"pkg\"\n\
+             [dependencies]\nserde = { workspace = true, features = [\"derive\"] }\n\
+             log.workspace = true\nrand = { workspace = true }\n"
+            .parse()
+            .unwrap();
+        assert_eq!(doc, expected);
+    }
+
+    #[test]
+    fn splits_the_remote_progress_into_its_phases() {
+        let mut pending = String::new();
+        assert_eq!(
+            remote_lines(&mut pending, b"Counting objects:  50% (1/2)\rCounting obj"),
+            [("Counting objects:  50% (1/2)".to_string(), false)]
+        );
+        assert_eq!(pending, "Counting obj");
+        assert_eq!(
+            remote_lines(
+                &mut pending,
+                b"ects: 100% (2/2), done.\nCompressing objects: 100% (1/1)\r"
+            ),
+            [
+                ("Counting objects: 100% (2/2), done.".to_string(), true),
+                ("Compressing objects: 100% (1/1)".to_string(), false),
+            ]
+        );
+        assert_eq!(remote_lines(&mut pending, b"Total 3 (delta 0)"), []);
+        // `\r\n` ends the line at `\r`, the empty one after it is not printed
+        assert_eq!(
+            remote_lines(&mut pending, b"\r\n"),
+            [
+                ("Total 3 (delta 0)".to_string(), false),
+                (String::new(), true)
+            ]
+        );
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn places_the_staged_project() {
+        let root = tempfile::tempdir().unwrap();
+        let staged = root.path().join(".contemplate-staged/pkg");
+        std::fs::create_dir_all(staged.join("src")).unwrap();
+        std::fs::write(staged.join("src/main.rs"), "fn main() {}\n").unwrap();
+        let dest = root.path().join("nested/demo");
+        place(&staged, &dest).unwrap();
+        assert!(dest.join("src/main.rs").is_file());
+        assert!(!staged.exists());
+
+        // the staged directory gone by the time it is moved
+        let (result, said) = said(|| place(&staged, &root.path().join("other")));
+        assert_eq!(result.unwrap_err().code(), 12);
+        assert_eq!(
+            said,
+            [format!(
+                "the package was staged at {}, but it is not there anymore",
+                staged.display()
+            )]
+        );
+        assert!(!root.path().join("other").exists());
+    }
+
+    #[test]
+    fn reads_the_entry_of_the_host_out_of_netrc() {
+        let content = "machine other.com login other password secret\n\
+                       machine example.com\n  login me\n  password hunter2\n";
+        assert_eq!(
+            parse_netrc(content, "example.com"),
+            Some(("me".to_string(), "hunter2".to_string()))
+        );
+        assert_eq!(parse_netrc(content, "example.org"), None);
+    }
+
+    #[test]
+    fn falls_back_to_the_default_netrc_entry() {
+        let content = "machine other.com login other password secret\n\
+                       default login anonymous password guest\n";
+        assert_eq!(
+            parse_netrc(content, "example.com"),
+            Some(("anonymous".to_string(), "guest".to_string()))
+        );
+        assert_eq!(
+            parse_netrc(content, "other.com"),
+            Some(("other".to_string(), "secret".to_string()))
+        );
+    }
+
+    #[test]
+    fn keeps_the_first_netrc_entry_of_the_host() {
+        let content = "machine example.com login first password one\n\
+                       machine example.com login second password two\n\
+                       default login anonymous password guest\n";
+        assert_eq!(
+            parse_netrc(content, "example.com"),
+            Some(("first".to_string(), "one".to_string()))
+        );
+    }
+
+    #[test]
+    fn needs_a_password_in_netrc() {
+        let content = "machine example.com login me\nmachine other.com password secret\n";
+        assert_eq!(parse_netrc(content, "example.com"), None);
+    }
+
+    #[test]
+    fn skips_netrc_macros() {
+        let content = "macdef init\n\
+                       machine example.com login macro password bad\n\
+                       \n\
+                       machine example.com login me password hunter2\n";
+        assert_eq!(
+            parse_netrc(content, "example.com"),
+            Some(("me".to_string(), "hunter2".to_string()))
+        );
+    }
+
+    #[test]
+    fn reads_netrc_from_the_environment_over_the_home_directory() {
+        let home = tempfile::tempdir().unwrap();
+        std::fs::write(
+            home.path().join(".netrc"),
+            "machine example.com login home password one\n",
+        )
+        .unwrap();
+        let netrc_file = home.path().join("netrc");
+        std::fs::write(&netrc_file, "machine example.com login env password two\n").unwrap();
+        let saved = (env::var_os("HOME"), env::var_os("NETRC"));
+        env::set_var("HOME", home.path());
+
+        env::set_var("NETRC", &netrc_file);
+        let from_env = netrc("example.com");
+        env::remove_var("NETRC");
+        let from_home = netrc("example.com");
+
+        for (name, value) in [("HOME", saved.0), ("NETRC", saved.1)] {
+            match value {
+                Some(value) => env::set_var(name, value),
+                None => env::remove_var(name),
+            }
+        }
+        assert_eq!(from_env, Some(("env".to_string(), "two".to_string())));
+        assert_eq!(from_home, Some(("home".to_string(), "one".to_string())));
+    }
+}
@@ -0,0 +1,178 @@
+//! `scaffold` called as a library, in a test binary of its own as it finds
+//! the registry through `CONTEMPLATE_HOME`, which is set for the process.
+
+use cargo_contemplate::{scaffold, Options, ProgressEvent};
+use git2::build::RepoBuilder;
+use git2::{Repository, Signature};
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+use tempfile::TempDir;
+
+fn write(dir: &Path, path: &str, content: &str) {
+    let path = dir.join(path);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, content).unwrap();
+}
+
+/// A registry with the class `fixture`, set up once for all the tests as
+/// `CONTEMPLATE_HOME` is the same for all of them.
+fn fixture() -> &'static Path {
+    static ROOT: OnceLock<TempDir> = OnceLock::new();
+    ROOT.get_or_init(|| {
+        let root = tempfile::tempdir().unwrap();
+        let src = root.path().join("src");
+        write(
+            &src,
+            "pkg/Cargo.toml",
+            "[package]\nname = \"pkg\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+        );
+        write(&src, "pkg/src/main.rs", "fn main() {}\n");
+        let repo = Repository::init(&src).unwrap();
+        let mut index = repo.index().unwrap();
+        index
+            .add_all(["*"], git2::IndexAddOption::DEFAULT, None)
+            .unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = Signature::now("fixture", "fixture@example.com").unwrap();
+        repo.commit(Some("HEAD"), &signature, &signature, "template", &tree, &[])
+            .unwrap();
+        let bare = root.path().join("template.git");
+        let url = |path: &Path| format!("file://{}", path.display());
+        RepoBuilder::new()
+            .bare(true)
+            .clone(&url(&src), &bare)
+            .unwrap();
+        let head = repo.head().unwrap();
+        let branch = head.shorthand().unwrap();
+        write(
+            &root.path().join("home"),
+            "templates.toml",
+            &format!(
+                "[templates.fixture]\nurl = \"{}\"\nbranch = \"{}\"\npackage = \"pkg\"\n",
+                url(&bare),
+                branch
+            ),
+        );
+        std::env::set_var("CONTEMPLATE_HOME", root.path().join("home"));
+        root
+    })
+    .path()
+}
+
+#[test]
+fn reports_the_progress_to_the_hook() {
+    fixture();
+    let root = tempfile::tempdir().unwrap();
+    let mut events = Vec::new();
+    let dest = root.path().join("demo");
+    let options = Options::default();
+    scaffold(
+        "fixture",
+        &dest,
+        &options,
+        Some(&mut |event| events.push(event)),
+    )
+    .unwrap();
+    let manifest = fs::read_to_string(dest.join("Cargo.toml")).unwrap();
+    assert!(manifest.contains("name = \"demo\""), "{}", manifest);
+
+    assert!(events.iter().any(|e| matches!(e, ProgressEvent::Fetch(_))));
+    let checkout = events.iter().rev().find_map(|event| match event {
+        ProgressEvent::Checkout { current, total, .. } => Some((*current, *total)),
+        _ => None,
+    });
+    assert!(matches!(checkout, Some((current, total)) if current == total && total > 0));
+    let copy = events.iter().rev().find_map(|event| match event {
+        ProgressEvent::Copy {
+            copied_bytes,
+            total_bytes,
+        } => Some((*copied_bytes, *total_bytes)),
+        _ => None,
+    });
+    assert!(matches!(copy, Some((copied, total)) if copied == total && total > 0));
+}
+
+#[test]
+fn returns_what_it_created() {
+    fixture();
+    let root = tempfile::tempdir().unwrap();
+    let dest = root.path().join("demo");
+    let options = Options {
+        vars: vec![("project_name".to_string(), "named".to_string())],
+        git_init: true,
+        ..Options::default()
+    };
+    let outcome = scaffold("fixture", &dest, &options, Some(&mut |_| {})).unwrap();
+    assert_eq!(outcome.path, dest);
+    let mut files = outcome.files.clone();
+    files.sort();
+    assert_eq!(files, [Path::new("Cargo.toml"), Path::new("src/main.rs")]);
+    assert_eq!(outcome.commit.map(|commit| commit.len()), Some(40));
+    assert!(outcome.messages.iter().any(|line| line.contains(" -> ")));
+    let manifest = fs::read_to_string(dest.join("Cargo.toml")).unwrap();
+    assert!(manifest.contains("name = \"named\""), "{}", manifest);
+    assert!(dest.join(".git").is_dir());
+}
+
+#[test]
+fn fails_with_what_went_wrong() {
+    fixture();
+    let root = tempfile::tempdir().unwrap();
+    let options = Options {
+        vars: vec![("project_name".to_string(), "not a name".to_string())],
+        ..Options::default()
+    };
+    let failure = scaffold("fixture", &root.path().join("demo"), &options, None).unwrap_err();
+    assert_eq!(failure.error.code(), 28);
+    assert_eq!(
+        failure.messages.last().unwrap(),
+        "`not a name` is not a valid crate name"
+    );
+    assert!(failure
+        .to_string()
+        .ends_with(failure.messages.last().unwrap()));
+    assert!(!root.path().join("demo").exists());
+}
+
+#[test]
+fn scaffolds_side_by_side() {
+    fixture();
+    let root = tempfile::tempdir().unwrap();
+    let dests: Vec<_> = (0..4)
+        .map(|i| root.path().join(format!("demo{}", i)))
+        .collect();
+    std::thread::scope(|scope| {
+        let runs: Vec<_> = dests
+            .iter()
+            .map(|dest| {
+                scope.spawn(move || {
+                    scaffold("fixture", dest, &Options::default(), Some(&mut |_| {}))
+                })
+            })
+            .collect();
+        for run in runs {
+            run.join().unwrap().unwrap();
+        }
+    });
+    for dest in &dests {
+        assert!(dest.join("src/main.rs").is_file(), "{}", dest.display());
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn scaffolds_into_a_destination_that_is_not_utf8() {
+    use std::os::unix::ffi::OsStrExt;
+
+    fixture();
+    let root = tempfile::tempdir().unwrap();
+    let dest = root.path().join(std::ffi::OsStr::from_bytes(b"d\xffmo"));
+    let options = Options {
+        vars: vec![("project_name".to_string(), "demo".to_string())],
+        ..Options::default()
+    };
+    let outcome = scaffold("fixture", &dest, &options, Some(&mut |_| {})).unwrap();
+    assert_eq!(outcome.path, dest);
+    assert!(dest.join("src/main.rs").is_file());
+}
@@ -0,0 +1,2753 @@
+use git2::build::RepoBuilder;
+use git2::{Repository, RepositoryInitOptions, Signature};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::thread;
+
+fn write(root: &Path, path: &str, content: &str) {
+    let path = root.join(path);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, content).unwrap();
+}
+
+fn file_url(path: &Path) -> String {
+    format!("file://{}", path.display())
+}
+
+/// Commits everything in `dir` to a new repository whose only branch is `main`.
+fn commit_all(dir: &Path) {
+    let repo =
+        Repository::init_opts(dir, RepositoryInitOptions::new().initial_head("main")).unwrap();
+    let mut index = repo.index().unwrap();
+    index
+        .add_all(["*"], git2::IndexAddOption::DEFAULT, None)
+        .unwrap();
+    let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+    let signature = Signature::now("fixture", "fixture@example.com").unwrap();
+    repo.commit(Some("HEAD"), &signature, &signature, "template", &tree, &[])
+        .unwrap();
+}
+
+/// A bare template repository registered as the `fixture` class, and an
+/// empty directory to scaffold into.
+struct Fixture {
+    root: tempfile::TempDir,
+}
+
+impl Fixture {
+    fn new() -> Fixture {
+        Fixture::with_files(&[])
+    }
+
+    /// Like `new`, with extra `(path, content)` files in the template repository.
+    fn with_files(files: &[(&str, &str)]) -> Fixture {
+        let root = tempfile::tempdir().unwrap();
+        let src = root.path().join("src");
+        write(
+            &src,
+            "pkg/Cargo.toml",
+            "[package]\nname = \"pkg\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+        );
+        write(&src, "pkg/src/main.rs", "fn main() {}\n");
+        write(&src, "README.md", "not part of the package\n");
+        for (path, content) in files {
+            write(&src, path, content);
+        }
+        commit_all(&src);
+
+        let bare = root.path().join("template.git");
+        RepoBuilder::new()
+            .bare(true)
+            .clone(&file_url(&src), &bare)
+            .unwrap();
+        write(
+            &root.path().join("home"),
+            "templates.toml",
+            &format!(
+                "[templates.fixture]\nurl = \"{}\"\nbranch = \"main\"\npackage = \"pkg\"\n",
+                file_url(&bare)
+            ),
+        );
+        fs::create_dir(root.path().join("work")).unwrap();
+        Fixture { root }
+    }
+
+    fn work(&self) -> PathBuf {
+        self.root.path().join("work")
+    }
+
+    /// Writes `(path, content)` files over the template and publishes them as
+    /// a new commit of its branch.
+    fn change_template(&self, files: &[(&str, &str)]) {
+        let src = self.root.path().join("src");
+        for (path, content) in files {
+            write(&src, path, content);
+        }
+        let repo = Repository::open(&src).unwrap();
+        let mut index = repo.index().unwrap();
+        index
+            .add_all(["*"], git2::IndexAddOption::DEFAULT, None)
+            .unwrap();
+        // files moved or removed in `src` are gone from the commit too
+        index.update_all(["*"], None).unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let parent = repo.head().unwrap().peel_to_commit().unwrap();
+        let signature = Signature::now("fixture", "fixture@example.com").unwrap();
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            "change",
+            &tree,
+            &[&parent],
+        )
+        .unwrap();
+        Repository::open(self.root.path().join("template.git"))
+            .unwrap()
+            .remote_anonymous(&file_url(&src))
+            .unwrap()
+            .fetch(&["+refs/heads/main:refs/heads/main"], None, None)
+            .unwrap();
+    }
+
+    /// Adds `entries`, `[templates.<class>]` tables, to the registry.
+    fn register(&self, entries: &str) {
+        let registry = self.root.path().join("home/templates.toml");
+        let mut content = fs::read_to_string(&registry).unwrap();
+        content.push_str(entries);
+        fs::write(registry, content).unwrap();
+    }
+
+    fn contemplate(&self, args: &[&str]) -> Output {
+        self.contemplate_with_env(args, &[])
+    }
+
+    /// Like `contemplate`, with extra environment variables.
+    fn contemplate_with_env(&self, args: &[&str], vars: &[(&str, &str)]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_cargo-contemplate"))
+            .arg("contemplate")
+            .args(args)
+            .current_dir(self.work())
+            .env("CONTEMPLATE_HOME", self.root.path().join("home"))
+            .envs(vars.iter().copied())
+            .output()
+            .unwrap()
+    }
+}
+
+fn entries(dir: &Path) -> Vec<String> {
+    let mut names: Vec<_> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn scaffolds_the_package() {
+    let fixture = Fixture::new();
+    let output = fixture.contemplate(&["fixture", "demo"]);
+    assert!(output.status.success(), "{:?}", output);
+
+    let demo = fixture.work().join("demo");
+    assert_eq!(entries(&fixture.work()), ["demo"]);
+    assert_eq!(entries(&demo), ["Cargo.toml", "src"]);
+    assert_eq!(
+        fs::read_to_string(demo.join("src/main.rs")).unwrap(),
+        "fn main() {}\n"
+    );
+}
+
+#[test]
+fn lists_the_branches_and_tags_without_cloning() {
+    let fixture = Fixture::new();
+    let bare = Repository::open(fixture.root.path().join("template.git")).unwrap();
+    let main = bare.refname_to_id("refs/heads/main").unwrap();
+    bare.reference("refs/heads/next", main, false, "next")
+        .unwrap();
+    let commit = bare.find_object(main, None).unwrap();
+    let signature = Signature::now("fixture", "fixture@example.com").unwrap();
+    bare.tag("v1", &commit, &signature, "v1", false).unwrap();
+
+    let output = fixture.contemplate(&["fixture", "--list-branches"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "* main\n  next\n"
+    );
+    let output = fixture.contemplate(&["fixture", "--list-branches", "--tags"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "* main\n  next\n  tags/v1\n"
+    );
+
+    let output = fixture.contemplate(&[
+        "fixture",
+        "--list-branches",
+        "--tags",
+        "--output-format",
+        "json",
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let main = main.to_string();
+    assert_eq!(
+        json["branches"],
+        serde_json::json!([
+            { "name": "main", "commit": main, "default": true },
+            { "name": "next", "commit": main, "default": false },
+        ])
+    );
+    // an annotated tag, listed once
+    assert_eq!(json["tags"].as_array().unwrap().len(), 1);
+    assert_eq!(json["tags"][0]["name"], "v1");
+    assert_eq!(entries(&fixture.work()), Vec::<String>::new());
+    assert!(!fixture.root.path().join("home/cache").exists());
+}
+
+#[test]
+fn prints_the_readme_of_the_template() {
+    let fixture = Fixture::new();
+    // the package has none, the repository does
+    let output = fixture.contemplate(&["fixture", "--template-readme"]);
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.ends_with("not part of the package\n"), "{}", stdout);
+
+    fixture.change_template(&[("pkg/README.md", "# pkg\n\nUse it.\n")]);
+    let output = fixture.contemplate(&["fixture", "--template-readme"]);
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.ends_with("# pkg\n\nUse it.\n"), "{}", stdout);
+    assert_eq!(entries(&fixture.work()), Vec::<String>::new());
+
+    let fixture = Fixture::new();
+    let src = fixture.root.path().join("src");
+    fs::remove_file(src.join("README.md")).unwrap();
+    fixture.change_template(&[]);
+    let output = fixture.contemplate(&["fixture", "--template-readme"]);
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.ends_with("the template has no README for pkg\n"),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn strips_the_remote_from_the_branch() {
+    let fixture = Fixture::new();
+    let bare = Repository::open(fixture.root.path().join("template.git")).unwrap();
+    let old = bare.refname_to_id("refs/heads/main").unwrap();
+    bare.reference("refs/heads/old", old, false, "old").unwrap();
+    fixture.change_template(&[("pkg/src/main.rs", "fn main() { todo!() }\n")]);
+
+    for (dest, branch) in [("a", "origin/old"), ("b", "refs/remotes/origin/old")] {
+        let output = fixture.contemplate(&["fixture", dest, "--branch", branch]);
+        assert!(output.status.success(), "{:?}", output);
+        let main = fs::read_to_string(fixture.work().join(dest).join("src/main.rs")).unwrap();
+        assert_eq!(main, "fn main() {}\n");
+    }
+}
+
+#[test]
+fn leaves_a_directory_named_after_the_package_alone() {
+    let fixture = Fixture::new();
+    write(&fixture.work(), "pkg/keep", "mine\n");
+    let output = fixture.contemplate(&["fixture", "demo"]);
+    assert!(output.status.success(), "{:?}", output);
+
+    assert_eq!(entries(&fixture.work()), ["demo", "pkg"]);
+    assert_eq!(entries(&fixture.work().join("pkg")), ["keep"]);
+    assert_eq!(entries(&fixture.work().join("demo")), ["Cargo.toml", "src"]);
+}
+
+#[test]
+fn copies_hidden_files() {
+    let fixture = Fixture::with_files(&[
+        (
+            "pkg/.cargo/config.toml",
+            "[build]\ntarget = \"wasm32-unknown-unknown\"\n",
+        ),
+        ("pkg/.editorconfig", "root = true\n"),
+    ]);
+    let output = fixture.contemplate(&["fixture", "demo"]);
+    assert!(output.status.success(), "{:?}", output);
+
+    let demo = fixture.work().join("demo");
+    assert_eq!(
+        entries(&demo),
+        [".cargo", ".editorconfig", "Cargo.toml", "src"]
+    );
+    assert_eq!(entries(&demo.join(".cargo")), ["config.toml"]);
+}
+
+#[test]
+fn leaves_out_editor_cruft() {
+    let cruft = [
+        ("pkg/.DS_Store", "\0\0\0\x01Bud1"),
+        ("pkg/.idea/workspace.xml", "<project/>\n"),
+        ("pkg/src/.main.rs.swp", "b0VIM"),
+    ];
+    let fixture = Fixture::with_files(&cruft);
+    let output = fixture.contemplate(&["fixture", "demo"]);
+    assert!(output.status.success(), "{:?}", output);
+    let demo = fixture.work().join("demo");
+    assert_eq!(entries(&demo), ["Cargo.toml", "src"]);
+    assert_eq!(entries(&demo.join("src")), ["main.rs"]);
+
+    let output = fixture.contemplate(&["fixture", "kept", "--no-cruft-filter"]);
+    assert!(output.status.success(), "{:?}", output);
+    let kept = fixture.work().join("kept");
+    assert_eq!(entries(&kept), [".DS_Store", ".idea", "Cargo.toml", "src"]);
+    assert_eq!(entries(&kept.join("src")), [".main.rs.swp", "main.rs"]);
+}
+
+#[test]
+fn scaffolds_into_a_nested_destination() {
+    let fixture = Fixture::new();
+    fs::create_dir(fixture.work().join("crates")).unwrap();
+    let output = fixture.contemplate(&["fixture", "crates/demo"]);
+    assert!(output.status.success(), "{:?}", output);
+
+    assert_eq!(entries(&fixture.work()), ["crates"]);
+    assert_eq!(
+        entries(&fixture.work().join("crates/demo")),
+        ["Cargo.toml", "src"]
+    );
+}
+
+#[test]
+fn scaffolds_into_the_current_directory() {
+    let fixture = Fixture::new();
+    let output = fixture.contemplate(&["fixture", ".", "--merge"]);
+    assert!(output.status.success(), "{:?}", output);
+
+    assert_eq!(entries(&fixture.work()), ["Cargo.toml", "src"]);
+    let manifest = fs::read_to_string(fixture.work().join("Cargo.toml")).unwrap();
+    assert!(manifest.contains("name = \"work\""), "{}", manifest);
+}
+
+#[test]
+fn scaffolds_into_a_destination_above_the_current_directory() {
+    let fixture = Fixture::new();
+    fs::create_dir(fixture.work().join("sub")).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_cargo-contemplate"))
+        .args(["contemplate", "fixture", "../demo"])
+        .current_dir(fixture.work().join("sub"))
+        .env("CONTEMPLATE_HOME", fixture.root.path().join("home"))
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+
+    assert_eq!(entries(&fixture.work()), ["demo", "sub"]);
+    assert_eq!(entries(&fixture.work().join("demo")), ["Cargo.toml", "src"]);
+}
+
+#[test]
+fn takes_the_destination_relative_to_the_repository_root() {
+    let fixture = Fixture::new();
+    let output = fixture.contemplate(&["fixture", "demo", "--relative-to-repo-root"]);
+    assert_eq!(output.status.code(), Some(33), "{:?}", output);
+    assert!(
+        String::from_utf8_lossy(&output.stdout).contains("needs to be run inside a git repository"),
+        "{:?}",
+        output
+    );
+
+    Repository::init(fixture.work()).unwrap();
+    fs::create_dir_all(fixture.work().join("sub/deeper")).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_cargo-contemplate"))
+        .args([
+            "contemplate",
+            "fixture",
+            "crates/demo",
+            "--relative-to-repo-root",
+        ])
+        .current_dir(fixture.work().join("sub/deeper"))
+        .env("CONTEMPLATE_HOME", fixture.root.path().join("home"))
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let demo = fixture.work().join("crates/demo");
+    assert_eq!(entries(&demo), ["Cargo.toml", "src"]);
+    assert_eq!(
+        entries(&fixture.work().join("sub/deeper")),
+        Vec::<String>::new()
+    );
+}
+
+#[test]
+fn scaffolds_into_a_destination_nested_in_missing_directories() {
+    let fixture = Fixture::new();
+    let output = fixture.contemplate(&["fixture", "nested/deeper/demo"]);
+    assert!(output.status.success(), "{:?}", output);
+
+    assert_eq!(entries(&fixture.work()), ["nested"]);
+    let demo = fixture.work().join("nested/deeper/demo");
+    assert_eq!(entries(&demo), ["Cargo.toml", "src"]);
+    let manifest = fs::read_to_string(demo.join("Cargo.toml")).unwrap();
+    assert!(manifest.contains("name = \"demo\""), "{}", manifest);
+}
+
+#[test]
+fn resolves_the_variables_in_the_destination_name() {
+    let fixture = Fixture::new();
+    let output = fixture.contemplate(&[
+        "fixture",
+        "crates/{{org}}-{{ name }}",
+        "--set",
+        "org=acme",
+        "--set",
+        "name=token",
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+    let manifest = fs::read_to_string(fixture.work().join("crates/acme-token/Cargo.toml")).unwrap();
+    assert!(manifest.contains("name = \"acme-token\""), "{}", manifest);
+
+    let output = fixture.contemplate(&["fixture", "{{org}}-svc"]);
+    assert_eq!(output.status.code(), Some(15), "{:?}", output);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("undefined variable `org`"));
+    // a value can't add directories of its own
+    let output = fixture.contemplate(&["fixture", "{{org}}", "--set", "org=acme/svc"]);
+    assert_eq!(output.status.code(), Some(16), "{:?}", output);
+    assert_eq!(entries(&fixture.work()), ["crates"]);
+}
+
+#[test]
+fn expands_the_home_directory_and_variables_in_the_destination() {
+    let fixture = Fixture::new();
+    let home = fixture.root.path().join("home");
+    let home = home.to_str().unwrap();
+    let output = fixture.contemplate_with_env(&["fixture", "~/tilde"], &[("HOME", home)]);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(fixture.root.path().join("home/tilde/Cargo.toml").is_file());
+
+    let crates = fixture.work().join("crates");
+    let crates = crates.to_str().unwrap();
+    let output =
+        fixture.contemplate_with_env(&["fixture", "${CRATES}/braced"], &[("CRATES", crates)]);
+    assert!(output.status.success(), "{:?}", output);
+    let output = fixture.contemplate_with_env(&["fixture", "$CRATES/plain"], &[("CRATES", crates)]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(entries(&fixture.work().join("crates")), ["braced", "plain"]);
+    assert!(!fixture.work().join("$CRATES").exists());
+
+    let output = fixture.contemplate(&["fixture", "$NO_SUCH_CONTEMPLATE_VAR/demo"]);
+    assert!(!output.status.success(), "{:?}", output);
+}
+
+#[test]
+fn takes_the_destination_and_flags_from_the_environment() {
+    let fixture = Fixture::new();
+    let env = [
+        ("CONTEMPLATE_DEST", "from-env"),
+        ("CONTEMPLATE_PACKAGE", "pkg"),
+    ];
+    let output = fixture.contemplate_with_env(&["fixture"], &env);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(fixture.work().join("from-env/Cargo.toml").is_file());
+
+    // the flag wins over the environment
+    let output = fixture.contemplate_with_env(&["fixture", "from-flag"], &env);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(fixture.work().join("from-flag/Cargo.toml").is_file());
+
+    // nor does CONTEMPLATE_DEST conflict with --clone-only
+    let output = fixture.contemplate_with_env(&["fixture", "--clone-only", "clone"], &env);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(fixture.work().join("clone/.git").is_dir());
+    let output = fixture.contemplate(&["fixture", "dest", "--clone-only", "clone"]);
+    assert!(!output.status.success(), "{:?}", output);
+
+    let env = [("CONTEMPLATE_PROXY", "not a url")];
+    let output = fixture.contemplate_with_env(&["fixture", "proxied"], &env);
+    assert_eq!(output.status.code(), Some(20), "{:?}", output);
+}
+
+#[test]
+fn scaffolds_into_a_destination_on_another_file_system() {
+    use std::os::unix::fs::MetadataExt;
+    let fixture = Fixture::new();
+    // the project is staged in the current directory, so a rename to a tmpfs
+    // fails with EXDEV, which only a copy gets around
+    let device = |path: &Path| fs::metadata(path).map(|meta| meta.dev()).ok();
+    let shm = Path::new("/dev/shm");
+    if device(shm).is_none() || device(shm) == device(&fixture.work()) {
+        eprintln!("skipped: /dev/shm is missing or on the same file system as the fixture");
+        return;
+    }
+    let other = tempfile::tempdir_in(shm).unwrap();
+    let dest = other.path().join("demo");
+    let output = fixture.contemplate(&["fixture", dest.to_str().unwrap()]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(entries(&dest), ["Cargo.toml", "src"]);
+    // nothing staged is left behind
+    assert_eq!(entries(&fixture.work()), Vec::<String>::new());
+}
+
+#[test]
+fn follows_a_template_whose_layout_changes() {
+    let fixture = Fixture::new();
+    let bare = Repository::open(fixture.root.path().join("template.git")).unwrap();
+    let old = bare.refname_to_id("refs/heads/main").unwrap().to_string();
+    let src = fixture.root.path().join("src");
+    fs::create_dir(src.join("crates")).unwrap();
+    fs::rename(src.join("pkg"), src.join("crates/pkg")).unwrap();
+    fixture.change_template(&[]);
+
+    let output = fixture.contemplate(&["fixture", "moved"]);
+    assert_eq!(output.status.code(), Some(27), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("the template has no `pkg` directory"),
+        "{}",
+        stdout
+    );
+    assert_eq!(entries(&fixture.work()), Vec::<String>::new());
+
+    let output = fixture.contemplate(&["fixture", "moved", "--package", "crates/pkg"]);
+    assert!(output.status.success(), "{:?}", output);
+    let manifest = fs::read_to_string(fixture.work().join("moved/Cargo.toml")).unwrap();
+    assert!(manifest.contains("name = \"moved\""), "{}", manifest);
+
+    // the revision from before the move still has it where the registry says
+    let output = fixture.contemplate(&["fixture", "old", "--rev", &old]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(entries(&fixture.work().join("old")), ["Cargo.toml", "src"]);
+}
+
+#[test]
+fn picks_the_package_with_template_subpath() {
+    let manifest = |name: &str| {
+        format!(
+            "[package]\nname = \"{}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+            name
+        )
+    };
+    let (one, two) = (manifest("one"), manifest("two"));
+    let fixture = Fixture::with_files(&[
+        ("crates/one/Cargo.toml", &one),
+        ("crates/one/src/lib.rs", ""),
+        ("crates/two/Cargo.toml", &two),
+        ("crates/two/src/lib.rs", ""),
+    ]);
+    let status = |output: &Output| {
+        (
+            output.status.code(),
+            String::from_utf8_lossy(&output.stdout).into_owned(),
+        )
+    };
+
+    let output = fixture.contemplate(&["fixture", "a", "--template-subpath", "crates/o*"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(entries(&fixture.work().join("a")), ["Cargo.toml", "src"]);
+    assert!(fixture.work().join("a/src/lib.rs").is_file());
+
+    let output = fixture.contemplate(&["fixture", "b", "--template-subpath", "crates/*"]);
+    let (code, stdout) = status(&output);
+    assert_eq!(code, Some(35), "{}", stdout);
+    assert!(
+        stdout.contains("`crates/*` matches crates/one, crates/two, pass --package to pick one"),
+        "{}",
+        stdout
+    );
+
+    let args = [
+        "fixture",
+        "b",
+        "--template-subpath",
+        "crates/*",
+        "--package",
+    ];
+    let output = fixture.contemplate(&[&args[..], &["crates/two"]].concat());
+    assert!(output.status.success(), "{:?}", output);
+    let manifest = fs::read_to_string(fixture.work().join("b/Cargo.toml")).unwrap();
+    assert!(manifest.contains("name = \"b\""), "{}", manifest);
+    assert!(fixture.work().join("b/src/lib.rs").is_file());
+
+    let args = [
+        "fixture",
+        "e",
+        "--template-subpath",
+        "crates/*",
+        "--package",
+        "pkg",
+    ];
+    let output = fixture.contemplate(&args);
+    let (code, stdout) = status(&output);
+    assert_eq!(code, Some(27), "{}", stdout);
+    assert!(
+        stdout.contains("`pkg` is not one of crates/one, crates/two"),
+        "{}",
+        stdout
+    );
+
+    // `*` stays within a directory
+    let output = fixture.contemplate(&["fixture", "c", "--template-subpath", "*/one"]);
+    assert!(output.status.success(), "{:?}", output);
+    let output = fixture.contemplate(&["fixture", "d", "--template-subpath", "*one"]);
+    let (code, stdout) = status(&output);
+    assert_eq!(code, Some(27), "{}", stdout);
+    assert!(
+        stdout.contains("no directory of the template matches `*one`"),
+        "{}",
+        stdout
+    );
+    assert_eq!(entries(&fixture.work()), ["a", "b", "c"]);
+}
+
+// the fallback only shows on a file system that tells cases apart
+#[cfg(target_os = "linux")]
+#[test]
+fn falls_back_to_a_package_that_differs_in_case() {
+    let manifest = "[package]\nname = \"app\"\nversion = \"0.1.0\"\nedition = \"2021\"\n";
+    let fixture = Fixture::with_files(&[
+        ("Crates/App/Cargo.toml", manifest),
+        ("Crates/App/src/main.rs", "fn main() {}\n"),
+        ("Twice/x", ""),
+        ("twice/x", ""),
+    ]);
+    let output = fixture.contemplate(&["fixture", "demo", "--package", "crates/app"]);
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("warning: the template has no `crates/app`, using `Crates/App`"),
+        "{}",
+        stdout
+    );
+    assert_eq!(entries(&fixture.work().join("demo")), ["Cargo.toml", "src"]);
+
+    let output = fixture.contemplate(&["fixture", "strict", "--package", "crates/app", "--strict"]);
+    assert_eq!(output.status.code(), Some(36), "{:?}", output);
+    let output = fixture.contemplate(&["fixture", "twice", "--package", "TWICE"]);
+    assert_eq!(output.status.code(), Some(35), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("`TWICE` matches Twice, twice when ignoring case")
+            || stdout.contains("`TWICE` matches twice, Twice when ignoring case"),
+        "{}",
+        stdout
+    );
+    let output = fixture.contemplate(&["fixture", "none", "--package", "crates/lib"]);
+    assert_eq!(output.status.code(), Some(27), "{:?}", output);
+    assert_eq!(entries(&fixture.work()), ["demo"]);
+}
+
+#[test]
+fn scaffolds_templates_from_local_directories() {
+    let fixture = Fixture::new();
+    let manifest = |name: &str| {
+        format!(
+            "[package]\nname = \"{}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+            name
+        )
+    };
+    let templates = fixture.root.path().join("templates");
+    write(&templates, "plain/Cargo.toml", &manifest("plain"));
+    write(&templates, "plain/src/main.rs", "fn main() {}\n");
+    write(&templates, "multi/app/Cargo.toml", &manifest("app"));
+    write(&templates, "multi/app/src/lib.rs", "");
+    write(&templates, "multi/README.md", "# multi\n");
+
+    let output = fixture.contemplate(&[
+        "--from-local",
+        templates.join("plain").to_str().unwrap(),
+        "a",
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(entries(&fixture.work().join("a")), ["Cargo.toml", "src"]);
+    let output = fixture.contemplate(&["--from-local", "nope", "b"]);
+    assert_eq!(output.status.code(), Some(10), "{:?}", output);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("`nope` is not a directory"));
+
+    let index = fixture.root.path().join("index.toml");
+    let index_path = index.to_str().unwrap();
+    fs::write(
+        &index,
+        "[templates.plain]\npath = \"templates/plain\"\n\n\
+         [templates.multi]\npath = \"templates/multi\"\npackage = \"app\"\n",
+    )
+    .unwrap();
+    // the index stands in for the registry
+    let output = fixture.contemplate(&["fixture", "c", "--index", index_path]);
+    assert_eq!(output.status.code(), Some(10), "{:?}", output);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("has no `fixture`"));
+    let output = fixture.contemplate(&["plain", "c", "--index", index_path]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(entries(&fixture.work().join("c")), ["Cargo.toml", "src"]);
+    let output = fixture.contemplate(&["multi", "d", "--index", index_path]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(entries(&fixture.work().join("d")), ["Cargo.toml", "src"]);
+    let manifest = fs::read_to_string(fixture.work().join("d/Cargo.toml")).unwrap();
+    assert!(manifest.contains("name = \"d\""), "{}", manifest);
+
+    let mut content = fs::read_to_string(&index).unwrap();
+    content.push_str("\n[templates.gone]\npath = \"templates/gone\"\n");
+    fs::write(&index, content).unwrap();
+    let output = fixture.contemplate(&["plain", "e", "--index", index_path]);
+    assert_eq!(output.status.code(), Some(37), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("these templates are not directories: gone ("),
+        "{}",
+        stdout
+    );
+    assert_eq!(entries(&fixture.work()), ["a", "c", "d"]);
+}
+
+#[test]
+fn scaffolds_into_a_destination_with_spaces() {
+    let fixture = Fixture::new();
+    fs::create_dir(fixture.work().join("my crates")).unwrap();
+    let output = fixture.contemplate(&[
+        "fixture",
+        "my crates/demo app",
+        "--set",
+        "project_name=demo",
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+
+    let demo = fixture.work().join("my crates/demo app");
+    assert_eq!(entries(&demo), ["Cargo.toml", "src"]);
+    assert!(fs::read_to_string(demo.join("Cargo.toml"))
+        .unwrap()
+        .contains("name = \"demo\""));
+
+    // the directory's name isn't made into a crate name
+    let output = fixture.contemplate(&["fixture", "my crates/other app"]);
+    assert_eq!(output.status.code(), Some(28), "{:?}", output);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("--set project_name="));
+    assert!(!fixture.work().join("my crates/other app").exists());
+}
+
+#[test]
+fn scaffolds_into_a_destination_with_non_ascii_characters() {
+    let fixture = Fixture::new();
+    fs::create_dir(fixture.work().join("projets")).unwrap();
+    let output = fixture.contemplate(&[
+        "fixture",
+        "projets/démo-項目",
+        "--set",
+        "project_name=demo",
+        "--merge",
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+
+    assert_eq!(entries(&fixture.work().join("projets")), ["démo-項目"]);
+    let demo = fixture.work().join("projets/démo-項目");
+    assert_eq!(entries(&demo), ["Cargo.toml", "src"]);
+    assert!(fs::read_to_string(demo.join("Cargo.toml"))
+        .unwrap()
+        .contains("name = \"demo\""));
+}
+
+#[test]
+fn tells_what_a_merge_would_do_with_dry_run() {
+    let fixture = Fixture::new();
+    let demo = fixture.work().join("demo");
+    write(&demo, "src/main.rs", "fn main() {\n    old();\n}\n");
+
+    let output = fixture.contemplate(&["fixture", "demo", "--merge", "--dry-run"]);
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("would add Cargo.toml\n"), "{}", stdout);
+    assert!(
+        stdout.contains("would skip src/main.rs, it already exists\n"),
+        "{}",
+        stdout
+    );
+
+    let output = fixture.contemplate(&["fixture", "demo", "--merge", "--overwrite", "--dry-run"]);
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.contains(
+            "would overwrite src/main.rs\n  @@ line 1\n  -fn main() {\n  -    old();\n  -}\n  +fn main() {}\n"
+        ),
+        "{}",
+        stdout
+    );
+    // nothing was written
+    assert_eq!(entries(&demo), ["src"]);
+    assert_eq!(
+        fs::read_to_string(demo.join("src/main.rs")).unwrap(),
+        "fn main() {\n    old();\n}\n"
+    );
+}
+
+#[test]
+fn follows_the_dest_exists_policy() {
+    let fixture = Fixture::new();
+    let demo = fixture.work().join("demo");
+    write(&demo, "Cargo.toml", "[package]\nname = \"mine\"\n");
+
+    let output = fixture.contemplate(&["fixture", "demo"]);
+    assert!(!output.status.success(), "{:?}", output);
+    // refused before the template is fetched
+    assert!(!String::from_utf8_lossy(&output.stdout).contains(" -> "));
+    let output = fixture.contemplate(&["fixture", "demo", "--dest-exists", "prompt"]);
+    assert!(!output.status.success(), "{:?}", output);
+    assert_eq!(entries(&demo), ["Cargo.toml"]);
+
+    let output = fixture.contemplate(&["fixture", "demo", "--dest-exists", "merge"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(entries(&demo), ["Cargo.toml", "src"]);
+    let manifest = fs::read_to_string(demo.join("Cargo.toml")).unwrap();
+    assert!(manifest.contains("mine"), "{}", manifest);
+
+    let output = fixture.contemplate(&["fixture", "demo", "--dest-exists", "overwrite"]);
+    assert!(output.status.success(), "{:?}", output);
+    let manifest = fs::read_to_string(demo.join("Cargo.toml")).unwrap();
+    assert!(manifest.contains("name = \"demo\""), "{}", manifest);
+}
+
+#[test]
+fn reads_the_variables_from_a_file() {
+    let fixture = Fixture::with_files(&[("pkg/NOTES.md", "{{greeting}} x{{count}} {{debug}}\n")]);
+    let vars = fixture.root.path().join("vars.toml");
+    let vars_path = vars.to_str().unwrap();
+    fs::write(&vars, "greeting = \"hi\"\ncount = 3\ndebug = false\n").unwrap();
+    let output = fixture.contemplate(&["fixture", "a", "--set-from-file", vars_path]);
+    assert!(output.status.success(), "{:?}", output);
+    let notes =
+        |name: &str| fs::read_to_string(fixture.work().join(name).join("NOTES.md")).unwrap();
+    assert_eq!(notes("a"), "hi x3 false\n");
+
+    let output = fixture.contemplate(&[
+        "fixture",
+        "b",
+        "--set-from-file",
+        vars_path,
+        "--set",
+        "greeting=hello",
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(notes("b"), "hello x3 false\n");
+
+    // with --strict, every variable of the file has to be used
+    fs::write(
+        &vars,
+        "greeting = \"hi\"\ncount = 3\ndebug = false\nextra = \"\"\n",
+    )
+    .unwrap();
+    let output = fixture.contemplate(&["fixture", "c", "--set-from-file", vars_path]);
+    assert!(output.status.success(), "{:?}", output);
+    let output = fixture.contemplate(&["fixture", "d", "--set-from-file", vars_path, "--strict"]);
+    assert_eq!(output.status.code(), Some(40), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("the template doesn't use `extra` from --set-from-file"),
+        "{}",
+        stdout
+    );
+
+    fs::write(&vars, "greeting = [\"hi\"]\n").unwrap();
+    let output = fixture.contemplate(&["fixture", "e", "--set-from-file", vars_path]);
+    assert_eq!(output.status.code(), Some(39), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("`greeting` is not a string, number or boolean"),
+        "{}",
+        stdout
+    );
+    fs::write(&vars, "greeting = \n").unwrap();
+    let output = fixture.contemplate(&["fixture", "e", "--set-from-file", vars_path]);
+    assert_eq!(output.status.code(), Some(39), "{:?}", output);
+    assert_eq!(entries(&fixture.work()), ["a", "b", "c"]);
+}
+
+#[test]
+fn renames_the_package_in_the_files_of_the_scope() {
+    let lock = "version = 3\n\n[[package]]\nname = \"pkg\"\nversion = \"0.1.0\"\n";
+    let fixture = Fixture::with_files(&[
+        ("pkg/Cargo.lock", lock),
+        ("pkg/README.md", "# pkg\n\nSee pkg.\n"),
+        ("pkg/package.json", "{ \"name\": \"pkg\" }\n"),
+    ]);
+    let read = |path: &str| fs::read_to_string(fixture.work().join(path)).unwrap();
+
+    let output = fixture.contemplate(&["fixture", "none"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(read("none/Cargo.lock"), lock);
+    assert_eq!(read("none/README.md"), "# pkg\n\nSee pkg.\n");
+
+    let output = fixture.contemplate(&["fixture", "some", "--rename-scope", "cargo-lock,readme"]);
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("renamed pkg to some in Cargo.lock\n"),
+        "{}",
+        stdout
+    );
+    assert!(
+        stdout.contains("renamed pkg to some in README.md\n"),
+        "{}",
+        stdout
+    );
+    assert!(read("some/Cargo.lock").contains("name = \"some\""));
+    assert_eq!(read("some/README.md"), "# some\n\nSee pkg.\n");
+    assert_eq!(read("some/package.json"), "{ \"name\": \"pkg\" }\n");
+
+    let output = fixture.contemplate(&["fixture", "every", "--rename-scope", "all"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(read("every/Cargo.lock").contains("name = \"every\""));
+    assert_eq!(read("every/README.md"), "# every\n\nSee pkg.\n");
+    assert_eq!(read("every/package.json"), "{ \"name\": \"every\" }\n");
+}
+
+#[test]
+fn cuts_the_package_loose_of_its_workspace() {
+    let fixture = Fixture::with_files(&[
+        (
+            "Cargo.toml",
+            "[workspace]\nmembers = [\"pkg\"]\n\n[workspace.package]\nversion = \"1.2.3\"\n\n\
+             [workspace.dependencies]\nserde = \"1\"\n",
+        ),
+        (
+            "pkg/Cargo.toml",
+            "[package]\nname = \"pkg\"\nversion.workspace = true\nedition.workspace = true\n\
+             publish = false\n\n[dependencies]\nserde = { workspace = true, features = [\"derive\"] }\n",
+        ),
+    ]);
+    let manifest = |name: &str| -> toml::Value {
+        let content = fs::read_to_string(fixture.work().join(name).join("Cargo.toml")).unwrap();
+        content.parse().unwrap()
+    };
+    let output = fixture.contemplate(&["fixture", "member"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        manifest("member")["package"]["version"]["workspace"],
+        true.into()
+    );
+
+    let output = fixture.contemplate(&["fixture", "alone", "--exclude-package-metadata"]);
+    assert!(output.status.success(), "{:?}", output);
+    let alone = manifest("alone");
+    let package = alone["package"].as_table().unwrap();
+    assert_eq!(package["version"], "1.2.3".into());
+    // neither the workspace nor the package knows it
+    assert!(!package.contains_key("edition"), "{:?}", package);
+    assert!(!package.contains_key("publish"), "{:?}", package);
+    let serde = alone["dependencies"]["serde"].as_table().unwrap();
+    assert_eq!(serde["version"], "1".into());
+    assert_eq!(serde["features"], toml::Value::Array(vec!["derive".into()]));
+    assert!(!serde.contains_key("workspace"), "{:?}", serde);
+}
+
+#[test]
+fn copies_the_template_verbatim_with_no_rewrite_and_no_substitute() {
+    let manifest = "[package]\nname = \"pkg\"\nversion = \"0.1.0\"\nedition = \"2021\"\n";
+    let fixture = Fixture::with_files(&[
+        ("pkg/NOTES.md", "# {{project_name}}\n"),
+        ("pkg/README.md", "# pkg\n"),
+    ]);
+    let read = |path: &str| fs::read_to_string(fixture.work().join(path)).unwrap();
+
+    let args = [
+        "--author",
+        "Ada",
+        "--license",
+        "MIT",
+        "--rename-scope",
+        "readme",
+    ];
+    let output = fixture.contemplate(&[&["fixture", "kept", "--no-rewrite"], &args[..]].concat());
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(read("kept/Cargo.toml"), manifest);
+    assert_eq!(read("kept/README.md"), "# pkg\n");
+    assert_eq!(read("kept/NOTES.md"), "# kept\n");
+
+    let output = fixture.contemplate(&["fixture", "unsubstituted", "--no-substitute"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(read("unsubstituted/Cargo.toml").contains("name = \"unsubstituted\""));
+    assert_eq!(read("unsubstituted/NOTES.md"), "# {{project_name}}\n");
+
+    let output = fixture.contemplate(&["fixture", "verbatim", "--no-rewrite", "--no-substitute"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(read("verbatim/Cargo.toml"), manifest);
+    assert_eq!(read("verbatim/NOTES.md"), "# {{project_name}}\n");
+}
+
+#[test]
+fn prints_the_plan_before_the_run() {
+    let fixture = Fixture::new();
+    let template = fixture.root.path().join("template.git");
+    let sha = Repository::open(&template)
+        .unwrap()
+        .refname_to_id("refs/heads/main")
+        .unwrap()
+        .to_string();
+    // away from the user's git config, which may name another default branch
+    let home = fixture.root.path().join("user");
+    fs::create_dir(&home).unwrap();
+    let env = [
+        ("HOME", home.to_str().unwrap()),
+        ("XDG_CONFIG_HOME", home.to_str().unwrap()),
+    ];
+    let output = fixture.contemplate_with_env(
+        &[
+            "fixture",
+            "demo",
+            "--print-plan",
+            "--license",
+            "MIT",
+            "--git-init",
+        ],
+        &env,
+    );
+    assert!(output.status.success(), "{:?}", output);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let demo = fixture.work().join("demo");
+    let plan = [
+        "plan:".to_string(),
+        format!("  source       {} (main)", file_url(&template)),
+        format!("  commit       {}", sha),
+        "  package      pkg".to_string(),
+        format!("  destination  {}", demo.display()),
+        "  substitute   project_name=demo".to_string(),
+        "  strip        .DS_Store, Thumbs.db, desktop.ini, .idea, .vscode, *.swp, *.swo, *~"
+            .to_string(),
+        "  Cargo.toml   name, license".to_string(),
+        "  git init     main".to_string(),
+    ];
+    assert_eq!(stderr.lines().collect::<Vec<_>>(), plan, "{}", stderr);
+    // and the run went ahead
+    assert!(demo.join("LICENSE").is_file());
+
+    let output =
+        fixture.contemplate(&["fixture", "json", "--print-plan", "--json", "--no-rewrite"]);
+    assert!(output.status.success(), "{:?}", output);
+    let plan: serde_json::Value = serde_json::from_slice(&output.stderr).unwrap();
+    assert_eq!(plan["package"], "pkg");
+    assert_eq!(plan["commit"], sha.as_str());
+    assert_eq!(plan["cargo_toml"], serde_json::json!([]));
+    assert_eq!(plan["git_init"], false);
+}
+
+const TOKENS: [(&str, &str); 2] = [
+    ("pkg/NOTES.md", "# {{project_name}}\n"),
+    ("pkg/src/lib.rs", "//! {{project_name}}, not {{unknown}}\n"),
+];
+
+#[test]
+fn checks_out_only_the_paths_asked_for() {
+    let fixture = Fixture::new();
+    let output = fixture.contemplate(&["fixture", "demo", "--checkout-path-spec", "pkg/src"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(entries(&fixture.work().join("demo")), ["src"]);
+
+    let output = fixture.contemplate(&["fixture", "other", "--checkout-path-spec", "nope"]);
+    assert_eq!(output.status.code(), Some(56));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("`nope`"));
+    assert_eq!(entries(&fixture.work()), ["demo"]);
+}
+
+#[test]
+fn flattens_the_package_when_asked() {
+    let fixture = Fixture::with_files(&[("pkg/config/.editorconfig", "root = true\n")]);
+    let output = fixture.contemplate(&["fixture", "demo", "--flatten"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        entries(&fixture.work().join("demo")),
+        [".editorconfig", "Cargo.toml", "main.rs"]
+    );
+
+    let fixture = Fixture::with_files(&[("pkg/config/main.rs", "// not the same\n")]);
+    let output = fixture.contemplate(&["fixture", "demo", "--flatten"]);
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("config/main.rs and src/main.rs"),
+        "{}",
+        stdout
+    );
+    assert_eq!(entries(&fixture.work()), Vec::<String>::new());
+}
+
+#[test]
+fn handles_empty_and_root_only_templates() {
+    let fixture = Fixture::new();
+    let empty = fixture.root.path().join("empty.git");
+    Repository::init_bare(&empty).unwrap();
+    let root = fixture.root.path().join("root");
+    write(
+        &root,
+        "Cargo.toml",
+        "[package]\nname = \"root\"\nversion = \"0.1.0\"\n",
+    );
+    write(&root, "src/main.rs", "fn main() {}\n");
+    write(&root, "contemplate.toml", "package = \".\"\n");
+    commit_all(&root);
+    fixture.register(&format!(
+        "[templates.empty]\nurl = \"{}\"\nbranch = \"main\"\npackage = \"pkg\"\n\
+         [templates.root]\nurl = \"{}\"\nbranch = \"main\"\n",
+        file_url(&empty),
+        file_url(&root)
+    ));
+
+    let output = fixture.contemplate(&["empty", "nothing"]);
+    assert_eq!(output.status.code(), Some(29), "{:?}", output);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("has no commits yet"));
+    assert!(!fixture.work().join("nothing").exists());
+
+    let output = fixture.contemplate(&["root", "whole"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        entries(&fixture.work().join("whole")),
+        ["Cargo.toml", "src"]
+    );
+}
+
+#[test]
+fn prefixes_the_crate_name() {
+    let fixture = Fixture::new();
+    let output = fixture.contemplate(&["fixture", "token", "--prefix", "acme-"]);
+    assert!(output.status.success(), "{:?}", output);
+    let manifest = fs::read_to_string(fixture.work().join("token/Cargo.toml")).unwrap();
+    assert!(manifest.contains("name = \"acme-token\""), "{}", manifest);
+
+    let output = fixture.contemplate(&[
+        "fixture",
+        "vault",
+        "--prefix",
+        "acme_",
+        "--prefix-dir",
+        "--set",
+        "project_name=safe",
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+    let manifest = fs::read_to_string(fixture.work().join("acme_vault/Cargo.toml")).unwrap();
+    assert!(manifest.contains("name = \"acme_safe\""), "{}", manifest);
+    assert_eq!(entries(&fixture.work()), ["acme_vault", "token"]);
+
+    let output = fixture.contemplate(&["fixture", "bad", "--prefix", "1-"]);
+    assert_eq!(output.status.code(), Some(28), "{:?}", output);
+}
+
+#[test]
+fn takes_the_package_from_the_template_manifest() {
+    let fixture = Fixture::with_files(&[
+        ("contemplate.toml", "package = \"moved\"\n"),
+        (
+            "moved/Cargo.toml",
+            "[package]\nname = \"moved\"\nversion = \"0.1.0\"\n",
+        ),
+        ("moved/src/lib.rs", "pub fn moved() {}\n"),
+    ]);
+    // over the registry's `package`
+    let output = fixture.contemplate(&["fixture", "manifest"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(fixture.work().join("manifest/src/lib.rs").is_file());
+    // and under --package
+    let output = fixture.contemplate(&["fixture", "flag", "--package", "pkg"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(fixture.work().join("flag/src/main.rs").is_file());
+
+    // with neither it, nor the registry naming one, there is no package
+    fixture.register(&format!(
+        "[templates.unnamed]\nurl = \"{}\"\nbranch = \"main\"\n",
+        file_url(&fixture.root.path().join("template.git"))
+    ));
+    fixture.change_template(&[("contemplate.toml", "")]);
+    let output = fixture.contemplate(&["fixture", "registry"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(fixture.work().join("registry/src/main.rs").is_file());
+    let output = fixture.contemplate(&["unnamed", "none"]);
+    assert_eq!(output.status.code(), Some(27), "{:?}", output);
+}
+
+#[test]
+fn checks_out_the_submodules_when_asked() {
+    let fixture = Fixture::new();
+    let vendored = fixture.root.path().join("vendored");
+    write(&vendored, "lib.rs", "pub fn vendored() {}\n");
+    commit_all(&vendored);
+    let src = Repository::open(fixture.root.path().join("src")).unwrap();
+    let mut submodule = src
+        .submodule(&file_url(&vendored), Path::new("pkg/vendor"), true)
+        .unwrap();
+    submodule.clone(None).unwrap();
+    submodule.add_finalize().unwrap();
+    fixture.change_template(&[]);
+
+    let output = fixture.contemplate(&["fixture", "with", "--checkout-submodules"]);
+    assert!(output.status.success(), "{:?}", output);
+    let vendor = fixture.work().join("with/vendor");
+    assert_eq!(entries(&vendor), ["lib.rs"]);
+
+    let output = fixture.contemplate(&["fixture", "without"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(!fixture.work().join("without/vendor/lib.rs").exists());
+}
+
+#[test]
+fn writes_the_license_asked_for() {
+    let fixture = Fixture::new();
+    let output = fixture.contemplate(&["fixture", "mit", "--license", "MIT"]);
+    assert!(output.status.success(), "{:?}", output);
+    let mit = fixture.work().join("mit");
+    assert_eq!(entries(&mit), ["Cargo.toml", "LICENSE", "src"]);
+    assert!(fs::read_to_string(mit.join("LICENSE"))
+        .unwrap()
+        .contains("MIT License"));
+    let manifest = fs::read_to_string(mit.join("Cargo.toml")).unwrap();
+    assert!(manifest.contains("license = \"MIT\""), "{}", manifest);
+
+    let output = fixture.contemplate(&["fixture", "dual", "--license", "MIT OR Apache-2.0"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        entries(&fixture.work().join("dual")),
+        ["Cargo.toml", "LICENSE-APACHE", "LICENSE-MIT", "src"]
+    );
+
+    // the template's own license is only replaced with --force
+    fixture.change_template(&[("pkg/LICENSE", "all rights reserved\n")]);
+    let output = fixture.contemplate(&["fixture", "kept", "--license", "Apache-2.0"]);
+    assert_eq!(output.status.code(), Some(22), "{:?}", output);
+    assert!(!fixture.work().join("kept").exists());
+    let output = fixture.contemplate(&["fixture", "kept", "--license", "Apache-2.0", "--force"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(fs::read_to_string(fixture.work().join("kept/LICENSE"))
+        .unwrap()
+        .contains("Apache License"));
+
+    let output = fixture.contemplate(&["fixture", "gpl", "--license", "GPL-3.0"]);
+    assert!(!output.status.success(), "{:?}", output);
+}
+
+#[test]
+fn leaves_out_the_files_of_a_false_variable() {
+    let fixture = Fixture::with_files(&[
+        (
+            "contemplate.toml",
+            "[conditional]\ndocker = [\"Dockerfile\", \"docker\"]\nci = [\".ci.yml\"]\n",
+        ),
+        ("pkg/Dockerfile", "FROM rust\n"),
+        ("pkg/docker/entrypoint.sh", "#!/bin/sh\n"),
+        ("pkg/.ci.yml", "jobs: {}\n"),
+    ]);
+    let output =
+        fixture.contemplate(&["fixture", "plain", "--set", "docker=Off", "--set", "ci=yes"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        entries(&fixture.work().join("plain")),
+        [".ci.yml", "Cargo.toml", "src"]
+    );
+
+    // unset, the files are kept
+    let output = fixture.contemplate(&["fixture", "full"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        entries(&fixture.work().join("full")),
+        [".ci.yml", "Cargo.toml", "Dockerfile", "docker", "src"]
+    );
+
+    fixture.change_template(&[(
+        "contemplate.toml",
+        "[conditional]\ndocker = [\"../README.md\"]\n",
+    )]);
+    let output = fixture.contemplate(&["fixture", "outside", "--set", "docker=0"]);
+    assert_eq!(output.status.code(), Some(18), "{:?}", output);
+}
+
+#[test]
+fn prints_the_post_create_message() {
+    let fixture = Fixture::with_files(&[(
+        "contemplate.toml",
+        "post_create_message = \"cd {{project_name}} && cargo run -- {{ mode }}\"\n",
+    )]);
+    let output = fixture.contemplate(&["fixture", "demo", "--set", "mode=fast"]);
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout
+            .lines()
+            .any(|line| line == "cd demo && cargo run -- fast"),
+        "{}",
+        stdout
+    );
+
+    // a message naming a variable that isn't set is printed as it is
+    let output = fixture.contemplate(&["fixture", "other"]);
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout
+            .lines()
+            .any(|line| line == "cd {{project_name}} && cargo run -- {{ mode }}"),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn substitutes_variables_in_every_file() {
+    let fixture = Fixture::with_files(&TOKENS);
+    let output = fixture.contemplate(&["fixture", "demo"]);
+    assert!(output.status.success(), "{:?}", output);
+
+    let demo = fixture.work().join("demo");
+    assert_eq!(
+        fs::read_to_string(demo.join("NOTES.md")).unwrap(),
+        "# demo\n"
+    );
+    assert_eq!(
+        fs::read_to_string(demo.join("src/lib.rs")).unwrap(),
+        "//! demo, not {{unknown}}\n"
+    );
+}
+
+#[test]
+fn substitutes_only_in_files_matching_replace_in() {
+    let fixture = Fixture::with_files(&TOKENS);
+    let output = fixture.contemplate(&["fixture", "demo", "--replace-in", "*.md"]);
+    assert!(output.status.success(), "{:?}", output);
+
+    let demo = fixture.work().join("demo");
+    assert_eq!(
+        fs::read_to_string(demo.join("NOTES.md")).unwrap(),
+        "# demo\n"
+    );
+    assert_eq!(
+        fs::read_to_string(demo.join("src/lib.rs")).unwrap(),
+        "//! {{project_name}}, not {{unknown}}\n"
+    );
+}
+
+#[test]
+fn takes_a_max_file_size_too_large_to_be_in_bytes() {
+    let fixture = Fixture::with_files(&TOKENS);
+    let max = u64::MAX.to_string();
+    let output = fixture.contemplate(&["fixture", "demo", "--max-file-size", &max]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        fs::read_to_string(fixture.work().join("demo/NOTES.md")).unwrap(),
+        "# demo\n"
+    );
+}
+
+/// A tar template whose `pkg/src/main.rs` was last modified at `mtime`.
+fn archive_modified_at(path: &Path, mtime: u64) {
+    archive_files_modified_at(path, mtime, &[("pkg/src/main.rs", "fn main() {}\n")]);
+}
+
+/// A tar template of `(path, content)` files, all last modified at `mtime`.
+fn archive_files_modified_at(path: &Path, mtime: u64, files: &[(&str, &str)]) {
+    let mut archive = tar::Builder::new(fs::File::create(path).unwrap());
+    for (name, content) in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(mtime);
+        archive
+            .append_data(&mut header, name, content.as_bytes())
+            .unwrap();
+    }
+    archive.finish().unwrap();
+}
+
+#[test]
+fn scaffolds_from_a_local_archive() {
+    let fixture = Fixture::new();
+    let files = [
+        (
+            "pkg/Cargo.toml",
+            "[package]\nname = \"pkg\"\nversion = \"0.1.0\"\n",
+        ),
+        ("pkg/src/main.rs", "fn main() {}\n"),
+    ];
+    let tar = fixture.root.path().join("template.tar.gz");
+    let mut archive = tar::Builder::new(flate2::write::GzEncoder::new(
+        fs::File::create(&tar).unwrap(),
+        flate2::Compression::default(),
+    ));
+    for (name, content) in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        archive
+            .append_data(&mut header, name, content.as_bytes())
+            .unwrap();
+    }
+    archive.into_inner().unwrap().finish().unwrap();
+    let zip = fixture.root.path().join("template.zip");
+    let mut archive = zip::ZipWriter::new(fs::File::create(&zip).unwrap());
+    for (name, content) in files {
+        archive
+            .start_file(name, zip::write::SimpleFileOptions::default())
+            .unwrap();
+        archive.write_all(content.as_bytes()).unwrap();
+    }
+    archive.finish().unwrap();
+
+    for (archive, dest) in [(&tar, "from-tar"), (&zip, "from-zip")] {
+        let output = fixture.contemplate(&[archive.to_str().unwrap(), dest]);
+        assert!(output.status.success(), "{:?}", output);
+        let demo = fixture.work().join(dest);
+        assert_eq!(entries(&demo), ["Cargo.toml", "src"]);
+        let manifest = fs::read_to_string(demo.join("Cargo.toml")).unwrap();
+        assert!(
+            manifest.contains(&format!("name = \"{}\"", dest)),
+            "{}",
+            manifest
+        );
+    }
+
+    // the package is the archive's one directory
+    let two = fixture.root.path().join("two.tar");
+    archive_files_modified_at(&two, 0, &[("a/main.rs", ""), ("b/main.rs", "")]);
+    let output = fixture.contemplate(&[two.to_str().unwrap(), "two"]);
+    assert_eq!(output.status.code(), Some(17), "{:?}", output);
+    assert!(!fixture.work().join("two").exists());
+}
+
+#[test]
+fn preserves_timestamps_only_when_asked() {
+    let fixture = Fixture::new();
+    let archive = fixture.root.path().join("template.tar");
+    archive_modified_at(&archive, 1_000_000_000);
+    let archive = archive.to_str().unwrap();
+    let modified = |project: &str| {
+        fs::metadata(fixture.work().join(project).join("src/main.rs"))
+            .and_then(|meta| meta.modified())
+            .unwrap()
+    };
+    let then = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000_000);
+
+    let output = fixture.contemplate(&[archive, "kept", "--preserve-timestamps"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(modified("kept"), then);
+
+    let output = fixture.contemplate(&[archive, "copied"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(modified("copied") > then);
+}
+
+#[test]
+fn preserves_timestamps_of_renamed_and_formatted_files() {
+    let fixture = Fixture::new();
+    let archive = fixture.root.path().join("template.tar");
+    archive_files_modified_at(
+        &archive,
+        1_000_000_000,
+        &[
+            (
+                "pkg/Cargo.toml",
+                "[package]\nname = \"pkg\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+            ),
+            ("pkg/src/{{bin}}.rs", "fn   main( ) {}\n"),
+        ],
+    );
+    let archive = archive.to_str().unwrap();
+    let modified = |path: &str| {
+        fs::metadata(fixture.work().join(path))
+            .and_then(|meta| meta.modified())
+            .unwrap()
+    };
+    let then = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000_000);
+
+    let output = fixture.contemplate(&[
+        archive,
+        "formatted",
+        "--set",
+        "bin=main",
+        "--fmt",
+        "--preserve-timestamps",
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+    let main = fs::read_to_string(fixture.work().join("formatted/src/main.rs")).unwrap();
+    assert_eq!(main, "fn main() {}\n");
+    assert_eq!(modified("formatted/src/main.rs"), then);
+    // rewritten with the project name, but from the template all the same
+    assert_eq!(modified("formatted/Cargo.toml"), then);
+
+    let output = fixture.contemplate(&[
+        archive,
+        "flat",
+        "--set",
+        "bin=main",
+        "--flatten",
+        "--preserve-timestamps",
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(modified("flat/main.rs"), then);
+}
+
+/// Serves a GitHub API with one release of `acme/tpl`, tagged `v1` and holding
+/// `asset` as `template.tar`, answering `requests` requests and handing back
+/// the head of each.
+fn serve_release(asset: Vec<u8>, requests: usize) -> (String, thread::JoinHandle<Vec<String>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let api = format!("http://{}", listener.local_addr().unwrap());
+    let release = format!(
+        "{{\"assets\": [{{\"name\": \"template.tar\", \"url\": \"{}/assets/1\"}}]}}",
+        api
+    );
+    let server = thread::spawn(move || {
+        let mut heads = Vec::new();
+        for stream in listener.incoming().take(requests) {
+            let mut stream = stream.unwrap();
+            let mut head = String::new();
+            let mut reader = BufReader::new(&stream);
+            while reader.read_line(&mut head).unwrap() > 2 {}
+            let body = match head.split_whitespace().nth(1) {
+                Some("/repos/acme/tpl/releases/tags/v1") => release.as_bytes(),
+                Some("/assets/1") => asset.as_slice(),
+                _ => b"",
+            };
+            let status = if body.is_empty() {
+                "404 Not Found"
+            } else {
+                "200 OK"
+            };
+            write!(
+                stream,
+                "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                status,
+                body.len()
+            )
+            .unwrap();
+            stream.write_all(body).unwrap();
+            heads.push(head);
+        }
+        heads
+    });
+    (api, server)
+}
+
+#[test]
+fn scaffolds_from_a_github_release_asset() {
+    let fixture = Fixture::new();
+    let archive = fixture.root.path().join("template.tar");
+    archive_modified_at(&archive, 0);
+    let (api, server) = serve_release(fs::read(&archive).unwrap(), 2);
+
+    let output = fixture.contemplate_with_env(
+        &["gh-release:acme/tpl@v1/template.tar", "demo"],
+        &[("GITHUB_API_URL", &api), ("GITHUB_TOKEN", "secret")],
+    );
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(entries(&fixture.work().join("demo/src")), ["main.rs"]);
+    let heads = server.join().unwrap();
+    assert_eq!(heads.len(), 2);
+    assert!(heads
+        .iter()
+        .all(|head| head.contains("Authorization: Bearer secret")));
+    // nothing of the download is left behind
+    assert_eq!(entries(&fixture.work()), ["demo"]);
+}
+
+#[test]
+fn rejects_a_missing_github_release_asset() {
+    let fixture = Fixture::new();
+    let (api, server) = serve_release(Vec::new(), 1);
+    let output = fixture.contemplate_with_env(
+        &["gh-release:acme/tpl@v1/other.tar.gz", "demo"],
+        &[("GITHUB_API_URL", &api)],
+    );
+    server.join().unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("template.tar"));
+    assert_eq!(entries(&fixture.work()), Vec::<String>::new());
+}
+
+#[test]
+fn resumes_or_refetches_a_cache_entry_left_behind() {
+    let fixture = Fixture::new();
+    let output = fixture.contemplate(&["fixture", "first", "--cache"]);
+    assert!(output.status.success(), "{:?}", output);
+    let entry = fs::read_dir(fixture.root.path().join("home/cache"))
+        .unwrap()
+        .flatten()
+        .map(|e| e.path())
+        .find(|p| p.is_dir())
+        .unwrap();
+
+    // as an interrupted first fetch leaves it, with nothing fetched yet
+    fs::remove_dir_all(&entry).unwrap();
+    Repository::init_bare(&entry).unwrap();
+    let output = fixture.contemplate(&["fixture", "resumed", "--cache"]);
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(!stdout.contains("is damaged"), "{}", stdout);
+    assert!(fixture.work().join("resumed/src/main.rs").is_file());
+
+    // a branch pointing at a commit that isn't there
+    fs::write(
+        entry.join("refs/heads/main"),
+        "1111111111111111111111111111111111111111\n",
+    )
+    .unwrap();
+    let output = fixture.contemplate(&["fixture", "refetched", "--cache"]);
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains(&format!(
+            "{} is damaged, fetching it again",
+            entry.display()
+        )),
+        "{}",
+        stdout
+    );
+    assert!(fixture.work().join("refetched/src/main.rs").is_file());
+}
+
+#[test]
+fn takes_turns_with_a_cache_entry() {
+    let fixture = Fixture::new();
+    let output = fixture.contemplate(&["fixture", "first", "--cache"]);
+    assert!(output.status.success(), "{:?}", output);
+    let entry = fs::read_dir(fixture.root.path().join("home/cache"))
+        .unwrap()
+        .flatten()
+        .map(|e| e.path())
+        .find(|p| p.is_dir())
+        .unwrap();
+    // as another run holds it
+    let lock = fs::File::open(entry.with_extension("lock")).unwrap();
+    fs2::FileExt::lock_exclusive(&lock).unwrap();
+
+    let output = fixture.contemplate(&["fixture", "impatient", "--cache", "--wait-for-lock", "1"]);
+    assert_eq!(output.status.code(), Some(38), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains(&format!("gave up waiting for {} after 1s", entry.display())),
+        "{}",
+        stdout
+    );
+    assert!(!fixture.work().join("impatient").exists());
+
+    let mut waiting = Command::new(env!("CARGO_BIN_EXE_cargo-contemplate"))
+        .args(["contemplate", "fixture", "patient", "--cache"])
+        .current_dir(fixture.work())
+        .env("CONTEMPLATE_HOME", fixture.root.path().join("home"))
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    let said = format!(
+        "waiting for another run to be done with {}",
+        entry.display()
+    );
+    let mut stdout = BufReader::new(waiting.stdout.take().unwrap()).lines();
+    assert!(stdout.any(|line| line.unwrap() == said));
+    drop(lock);
+    let rest: Vec<_> = stdout.map(|line| line.unwrap()).collect();
+    assert!(waiting.wait().unwrap().success(), "{:?}", rest);
+    assert!(fixture.work().join("patient/src/main.rs").is_file());
+}
+
+#[test]
+fn adds_another_branch_to_the_cache_entry_of_its_repository() {
+    let fixture = Fixture::new();
+    let entry = || {
+        let cache = fs::read_dir(fixture.root.path().join("home/cache")).unwrap();
+        cache
+            .flatten()
+            .map(|e| e.path())
+            .find(|p| p.is_dir())
+            .unwrap()
+    };
+    // the number of objects in each pack, from the fanout table of its index
+    let packs = || -> BTreeMap<String, u32> {
+        let packs = fs::read_dir(entry().join("objects/pack"))
+            .unwrap()
+            .flatten();
+        packs
+            .map(|e| e.path())
+            .filter(|p| p.extension().is_some_and(|e| e == "idx"))
+            .map(|idx| {
+                let bytes = fs::read(&idx).unwrap();
+                let count = u32::from_be_bytes(bytes[1028..1032].try_into().unwrap());
+                (
+                    idx.file_name().unwrap().to_string_lossy().into_owned(),
+                    count,
+                )
+            })
+            .collect()
+    };
+    let output = fixture.contemplate(&["fixture", "demo", "--cache"]);
+    assert!(output.status.success(), "{:?}", output);
+    let first = packs();
+    assert_eq!(first.len(), 1, "{:?}", first);
+
+    // next is main and one more commit
+    let bare = Repository::open(fixture.root.path().join("template.git")).unwrap();
+    let main = bare.refname_to_id("refs/heads/main").unwrap();
+    fixture.change_template(&[("pkg/src/main.rs", "fn main() { todo!() }\n")]);
+    let next = bare.refname_to_id("refs/heads/main").unwrap();
+    bare.reference("refs/heads/next", next, false, "next")
+        .unwrap();
+    bare.reference("refs/heads/main", main, true, "main")
+        .unwrap();
+    fixture.register(&format!(
+        "[templates.next]\nurl = \"{}\"\nbranch = \"next\"\npackage = \"pkg\"\n",
+        file_url(&fixture.root.path().join("template.git"))
+    ));
+    let output = fixture.contemplate(&["next", "other", "--cache"]);
+    assert!(output.status.success(), "{:?}", output);
+    let main = fs::read_to_string(fixture.work().join("other/src/main.rs")).unwrap();
+    assert_eq!(main, "fn main() { todo!() }\n");
+
+    let repo = Repository::open_bare(entry()).unwrap();
+    assert!(repo.find_reference("refs/heads/main").is_ok());
+    assert!(repo.find_reference("refs/heads/next").is_ok());
+    // main's objects are kept, and next only brought its commit, the trees
+    // down to pkg/src and the new main.rs
+    let second = packs();
+    assert!(
+        first.keys().all(|pack| second.contains_key(pack)),
+        "{:?}",
+        second
+    );
+    let added: Vec<_> = second
+        .iter()
+        .filter(|(pack, _)| !first.contains_key(*pack))
+        .map(|(_, count)| *count)
+        .collect();
+    assert_eq!(added, [5], "{:?}", second);
+}
+
+#[test]
+fn only_fetches_from_the_allowed_hosts() {
+    let fixture = Fixture::new();
+    let home = fixture.root.path().join("home");
+    fs::write(
+        home.join("config.toml"),
+        "allowed_hosts = [\"github.com\"]\n",
+    )
+    .unwrap();
+    let mut registry = fs::read_to_string(home.join("templates.toml")).unwrap();
+    registry.push_str(
+        "[templates.elsewhere]\nurl = \"https://example.com/tpl.git\"\nbranch = \"main\"\n",
+    );
+    fs::write(home.join("templates.toml"), registry).unwrap();
+
+    let output = fixture.contemplate(&["elsewhere", "demo"]);
+    assert_eq!(output.status.code(), Some(57));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("example.com is not one of"));
+    assert_eq!(entries(&fixture.work()), Vec::<String>::new());
+
+    // the fixture is on this machine
+    let output = fixture.contemplate(&["fixture", "demo"]);
+    assert!(output.status.success(), "{:?}", output);
+}
+
+#[test]
+fn fails_instead_of_fetching_from_the_network_with_no_network() {
+    let fixture = Fixture::new();
+    let output = fixture.contemplate_with_env(
+        &[
+            "gh-release:acme/tpl@v1/template.tar",
+            "demo",
+            "--no-network",
+        ],
+        &[("GITHUB_API_URL", "http://127.0.0.1:9")],
+    );
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("--no-network"));
+    assert_eq!(entries(&fixture.work()), Vec::<String>::new());
+
+    // the fixture is on this machine, so it doesn't count
+    let output = fixture.contemplate(&["fixture", "demo", "--no-network"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(entries(&fixture.work()), ["demo"]);
+}
+
+#[test]
+fn keeps_only_the_essentials_with_minimal() {
+    let fixture = Fixture::with_files(&[
+        (
+            "pkg/Cargo.toml",
+            "[package]\nname = \"pkg\"\nversion = \"0.1.0\"\n\n[[example]]\nname = \"demo\"\n",
+        ),
+        ("pkg/README.md", "# pkg\n"),
+        ("pkg/src/lib/util.rs", "\n"),
+        ("pkg/examples/demo.rs", "fn main() {}\n"),
+        ("pkg/docs/guide.md", "\n"),
+        ("pkg/.github/workflows/ci.yml", "\n"),
+        ("pkg/build.rs", "fn main() {}\n"),
+    ]);
+    let output = fixture.contemplate(&["fixture", "demo", "--minimal"]);
+    assert!(output.status.success(), "{:?}", output);
+    let demo = fixture.work().join("demo");
+    assert_eq!(entries(&demo), ["Cargo.toml", "README.md", "src"]);
+    assert_eq!(entries(&demo.join("src")), ["lib", "main.rs"]);
+    let manifest = fs::read_to_string(demo.join("Cargo.toml")).unwrap();
+    assert!(!manifest.contains("[[example]]"), "{}", manifest);
+
+    let output = fixture.contemplate(&[
+        "fixture",
+        "more",
+        "--minimal",
+        "--minimal-include",
+        "build.rs",
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        entries(&fixture.work().join("more")),
+        ["Cargo.toml", "README.md", "build.rs", "src"]
+    );
+}
+
+/// A template whose package is a workspace, the first member inheriting its
+/// metadata and the second one having its own.
+const WORKSPACE: [(&str, &str); 5] = [
+    (
+        "ws/Cargo.toml",
+        "[workspace]\nmembers = [\"crates/*\"]\n\n[workspace.package]\nauthors = [\"Template <t@example.com>\"]\nlicense = \"MIT\"\n",
+    ),
+    (
+        "ws/crates/core/Cargo.toml",
+        "[package]\nname = \"core\"\nauthors.workspace = true\nlicense.workspace = true\n",
+    ),
+    ("ws/crates/core/src/lib.rs", "\n"),
+    (
+        "ws/crates/cli/Cargo.toml",
+        "[package]\nname = \"cli\"\nauthors = [\"Template <t@example.com>\"]\nlicense = \"MIT\"\n",
+    ),
+    ("ws/crates/cli/src/main.rs", "// {{project_name}}\n"),
+];
+
+#[test]
+fn scaffolds_a_workspace() {
+    let fixture = Fixture::with_files(&WORKSPACE);
+    let output = fixture.contemplate(&[
+        "fixture",
+        "demo",
+        "--package",
+        "ws",
+        "--author",
+        "Me",
+        "--email",
+        "me@example.com",
+        "--license",
+        "Apache-2.0",
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+
+    let demo = fixture.work().join("demo");
+    let read = |path: &str| fs::read_to_string(demo.join(path)).unwrap();
+    let root = read("Cargo.toml");
+    assert!(!root.contains("[package]"), "{}", root);
+    assert!(
+        root.contains("authors = [\"Me <me@example.com>\"]"),
+        "{}",
+        root
+    );
+    assert!(root.contains("license = \"Apache-2.0\""), "{}", root);
+    let core = read("crates/core/Cargo.toml");
+    assert!(core.contains("authors.workspace = true"), "{}", core);
+    assert!(core.contains("license.workspace = true"), "{}", core);
+    let cli = read("crates/cli/Cargo.toml");
+    assert!(
+        cli.contains("authors = [\"Me <me@example.com>\"]"),
+        "{}",
+        cli
+    );
+    assert!(cli.contains("license = \"Apache-2.0\""), "{}", cli);
+    assert_eq!(read("crates/cli/src/main.rs"), "// demo\n");
+}
+
+#[test]
+fn resolves_a_plain_rev_as_a_branch_then_a_tag_then_a_commit() {
+    let fixture = Fixture::new();
+    let bare = Repository::open(fixture.root.path().join("template.git")).unwrap();
+    let first = bare.head().unwrap().peel_to_commit().unwrap();
+    bare.tag_lightweight("v1", first.as_object(), false)
+        .unwrap();
+    fixture.change_template(&[("pkg/src/second.rs", "")]);
+    let second = bare.head().unwrap().peel_to_commit().unwrap();
+    bare.branch("v1", &second, false).unwrap();
+
+    let has_second = |dest: &str, args: &[&str]| {
+        let output = fixture.contemplate(&[&["fixture", dest, "--rev"], args].concat());
+        assert!(output.status.success(), "{:?}", output);
+        fixture.work().join(dest).join("src/second.rs").exists()
+    };
+    assert!(has_second("branch", &["v1"]));
+    assert!(!has_second("tag", &["v1", "--ref-type", "tag"]));
+    let sha = first.id().to_string();
+    assert!(!has_second("commit", &[&sha]));
+    assert!(!has_second("typed", &[&sha, "--ref-type", "commit"]));
+
+    let output = fixture.contemplate(&["fixture", "bad", "--rev", "v1", "--ref-type", "commit"]);
+    assert_eq!(output.status.code(), Some(32), "{:?}", output);
+    let output = fixture.contemplate(&["fixture", "none", "--rev", "v2"]);
+    assert_eq!(output.status.code(), Some(19), "{:?}", output);
+}
+
+#[test]
+fn scaffolds_from_a_fully_qualified_ref() {
+    let fixture = Fixture::new();
+    let bare = Repository::open(fixture.root.path().join("template.git")).unwrap();
+    let wip = bare.refname_to_id("refs/heads/main").unwrap();
+    bare.reference("refs/custom/template", wip, false, "wip")
+        .unwrap();
+    fixture.change_template(&[("pkg/src/main.rs", "fn main() { todo!() }\n")]);
+
+    let output = fixture.contemplate(&["fixture", "demo", "--rev", "refs/custom/template"]);
+    assert!(output.status.success(), "{:?}", output);
+    let main = fs::read_to_string(fixture.work().join("demo/src/main.rs")).unwrap();
+    assert_eq!(main, "fn main() {}\n");
+
+    let output = fixture.contemplate(&["fixture", "other", "--rev", "refs/custom/nope"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("has no `refs/custom/nope`"));
+}
+
+#[test]
+fn keeps_the_template_history_when_asked() {
+    let fixture = Fixture::new();
+    let output = fixture.contemplate(&["fixture", "demo", "--package", ".", "--keep-git"]);
+    assert!(output.status.success(), "{:?}", output);
+    let repo = Repository::open(fixture.work().join("demo")).unwrap();
+    let head = repo.head().unwrap().peel_to_commit().unwrap();
+    assert_eq!(head.summary(), Some("template"));
+    let origin = repo.find_remote("origin").unwrap();
+    assert_eq!(
+        origin.url(),
+        Some(file_url(&fixture.root.path().join("template.git")).as_str())
+    );
+
+    let output = fixture.contemplate(&["fixture", "plain", "--package", "."]);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(!fixture.work().join("plain/.git").exists());
+}
+
+#[test]
+fn renames_or_clears_the_remote_of_the_kept_history() {
+    let fixture = Fixture::new();
+    let url = "https://example.com/me/demo.git";
+    let output = fixture.contemplate(&[
+        "fixture",
+        "demo",
+        "--package",
+        ".",
+        "--keep-git",
+        "--rename-git-remote",
+        url,
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+    let repo = Repository::open(fixture.work().join("demo")).unwrap();
+    assert_eq!(repo.find_remote("origin").unwrap().url(), Some(url));
+
+    let output = fixture.contemplate(&[
+        "fixture",
+        "cleared",
+        "--package",
+        ".",
+        "--keep-git",
+        "--clear-git-remote",
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+    let repo = Repository::open(fixture.work().join("cleared")).unwrap();
+    assert!(repo.remotes().unwrap().is_empty());
+}
+
+#[test]
+fn fails_on_any_warning_with_strict() {
+    let fixture = Fixture::with_files(&[("pkg/.DS_Store", "")]);
+    let output = fixture.contemplate(&["fixture", "lenient"]);
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("warning: the template has .DS_Store, leaving it out"),
+        "{}",
+        stdout
+    );
+    let output = fixture.contemplate(&["fixture", "strict", "--strict"]);
+    assert_eq!(output.status.code(), Some(36), "{:?}", output);
+
+    let fixture = Fixture::new();
+    let output = fixture.contemplate(&["fixture", "lenient", "--keep-git"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(!fixture.work().join("lenient/.git").exists());
+    let output = fixture.contemplate(&["fixture", "strict", "--keep-git", "--strict"]);
+    assert_eq!(output.status.code(), Some(36), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains(
+            "warning: only a template that is a whole git repository can keep its history"
+        ),
+        "{}",
+        stdout
+    );
+    assert_eq!(entries(&fixture.work()), ["lenient"]);
+}
+
+#[test]
+fn keeps_the_template_history_when_the_config_says_so() {
+    let fixture = Fixture::new();
+    let config = fixture.root.path().join("home/config.toml");
+    fs::write(&config, "[defaults]\nkeep_git = true\n").unwrap();
+    let output = fixture.contemplate(&["fixture", "demo", "--package", "."]);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(fixture.work().join("demo/.git").is_dir());
+
+    let output = fixture.contemplate(&["fixture", "plain", "--package", ".", "--no-keep-git"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(!fixture.work().join("plain/.git").exists());
+
+    // nor does the default conflict with what --keep-git does
+    let output = fixture.contemplate(&[
+        "fixture",
+        "partial",
+        "--package",
+        ".",
+        "--checkout-path-spec",
+        "pkg",
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(!fixture.work().join("partial/.git").exists());
+
+    fs::write(&config, "[defaults]\nkeep_git = false\n").unwrap();
+    let output = fixture.contemplate(&[
+        "fixture",
+        "cleared",
+        "--package",
+        ".",
+        "--keep-git",
+        "--clear-git-remote",
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(fixture.work().join("cleared/.git").is_dir());
+}
+
+#[test]
+fn clones_the_template_with_clone_only() {
+    let fixture = Fixture::new();
+    let output = fixture.contemplate(&["--clone-only", "clone", "fixture"]);
+    assert!(output.status.success(), "{:?}", output);
+    let repo = Repository::open(fixture.work().join("clone")).unwrap();
+    let head = repo.head().unwrap().peel_to_commit().unwrap();
+    assert_eq!(head.summary(), Some("template"));
+    let origin = repo.find_remote("origin").unwrap();
+    assert_eq!(
+        origin.url(),
+        Some(file_url(&fixture.root.path().join("template.git")).as_str())
+    );
+
+    let output = fixture.contemplate(&["--clone-only", "clone", "fixture"]);
+    assert!(!output.status.success(), "{:?}", output);
+}
+
+#[test]
+fn substitutes_only_in_marked_files_in_marker_mode() {
+    let fixture = Fixture::with_files(&[
+        (
+            "pkg/src/lib.rs",
+            "// contemplate: template\npub const NAME: &str = \"{{project_name}}\";\n",
+        ),
+        (
+            "pkg/NOTES.md",
+            "<!-- contemplate: template -->\n# {{project_name}}\n",
+        ),
+        ("pkg/docs.md", "{{project_name}} is left alone\n"),
+    ]);
+    let output = fixture.contemplate(&["fixture", "demo", "--substitution-mode", "marker"]);
+    assert!(output.status.success(), "{:?}", output);
+    let demo = fixture.work().join("demo");
+    assert_eq!(
+        fs::read_to_string(demo.join("src/lib.rs")).unwrap(),
+        "pub const NAME: &str = \"demo\";\n"
+    );
+    assert_eq!(
+        fs::read_to_string(demo.join("NOTES.md")).unwrap(),
+        "# demo\n"
+    );
+    assert_eq!(
+        fs::read_to_string(demo.join("docs.md")).unwrap(),
+        "{{project_name}} is left alone\n"
+    );
+
+    let output = fixture.contemplate(&["fixture", "none", "--substitution-mode", "none"]);
+    assert!(output.status.success(), "{:?}", output);
+    let notes = fs::read_to_string(fixture.work().join("none/NOTES.md")).unwrap();
+    assert!(notes.contains("{{project_name}}"), "{}", notes);
+}
+
+#[test]
+fn substitutes_variables_in_file_names() {
+    let fixture = Fixture::with_files(&[
+        ("pkg/src/{{module}}.rs", "pub fn {{module}}() {}\n"),
+        ("pkg/{{module}}_data/{{ module }}.json", "{}\n"),
+        ("pkg/assets/{{module}}", "\n"),
+    ]);
+    let output = fixture.contemplate(&["fixture", "demo", "--set", "module=vault"]);
+    assert!(output.status.success(), "{:?}", output);
+    let demo = fixture.work().join("demo");
+    assert_eq!(entries(&demo.join("src")), ["main.rs", "vault.rs"]);
+    assert_eq!(
+        fs::read_to_string(demo.join("src/vault.rs")).unwrap(),
+        "pub fn vault() {}\n"
+    );
+    assert!(demo.join("vault_data/vault.json").is_file());
+    assert!(demo.join("assets/vault").is_file());
+
+    for (module, error) in [
+        ("main", "already exists"),
+        ("..", "would be named"),
+        ("a/b", "would be named"),
+    ] {
+        let var = format!("module={}", module);
+        let output = fixture.contemplate(&["fixture", "bad", "--set", &var]);
+        assert!(!output.status.success(), "{}: {:?}", module, output);
+        assert!(String::from_utf8_lossy(&output.stdout).contains(error));
+        assert!(!fixture.work().join("bad").exists());
+    }
+}
+
+#[test]
+fn rejects_an_empty_package() {
+    let fixture = Fixture::new();
+    let template = fixture.root.path().join("local");
+    write(&template, "README.md", "an empty package\n");
+    fs::create_dir_all(template.join("empty/src")).unwrap();
+    let class = template.to_str().unwrap();
+    let output = fixture.contemplate(&["--from-local", class, "demo", "--package", "empty"]);
+    assert!(!output.status.success(), "{:?}", output);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("--allow-empty"));
+    assert_eq!(entries(&fixture.work()), Vec::<String>::new());
+
+    let output = fixture.contemplate(&[
+        "--from-local",
+        class,
+        "demo",
+        "--package",
+        "empty",
+        "--allow-empty",
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(fixture.work().join("demo").is_dir());
+}
+
+#[test]
+fn prints_the_project_as_a_tree() {
+    let fixture = Fixture::new();
+    let output = fixture.contemplate(&["fixture", "demo", "--tree"]);
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let tree = format!(
+        "{}\n├── Cargo.toml\n└── src\n    └── main.rs\n",
+        fixture.work().join("demo").display()
+    );
+    assert!(stdout.ends_with(&tree), "{}", stdout);
+}
+
+#[test]
+fn starts_the_repository_on_the_default_branch_of_git() {
+    let fixture = Fixture::new();
+    let home = fixture.root.path().join("user");
+    fs::create_dir(&home).unwrap();
+    let env = [
+        ("HOME", home.to_str().unwrap()),
+        ("XDG_CONFIG_HOME", home.to_str().unwrap()),
+    ];
+    let head = |name: &str| {
+        let repo = Repository::open(fixture.work().join(name)).unwrap();
+        let head = repo.head().unwrap();
+        head.shorthand().unwrap().to_string()
+    };
+    let output = fixture.contemplate_with_env(&["fixture", "unset", "--git-init"], &env);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(head("unset"), "main");
+
+    write(&home, ".gitconfig", "[init]\n\tdefaultBranch = trunk\n");
+    let output = fixture.contemplate_with_env(&["fixture", "configured", "--git-init"], &env);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(head("configured"), "trunk");
+}
+
+#[test]
+fn takes_the_author_and_email_from_the_flags_over_git() {
+    let fixture = Fixture::new();
+    let home = fixture.root.path().join("user");
+    write(
+        &home,
+        ".gitconfig",
+        "[user]\n\tname = Configured\n\temail = configured@example.com\n",
+    );
+    let env = [
+        ("HOME", home.to_str().unwrap()),
+        ("XDG_CONFIG_HOME", home.to_str().unwrap()),
+    ];
+    let authors = |name: &str| {
+        let manifest = fs::read_to_string(fixture.work().join(name).join("Cargo.toml")).unwrap();
+        let manifest: toml::Value = manifest.parse().unwrap();
+        manifest["package"].get("authors").cloned()
+    };
+    let committer = |name: &str| {
+        let repo = Repository::open(fixture.work().join(name)).unwrap();
+        let commit = repo.head().unwrap().peel_to_commit().unwrap();
+        let author = commit.author();
+        format!("{} <{}>", author.name().unwrap(), author.email().unwrap())
+    };
+
+    let output = fixture.contemplate_with_env(&["fixture", "plain", "--git-init"], &env);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(authors("plain"), None);
+    assert_eq!(committer("plain"), "Configured <configured@example.com>");
+
+    let output = fixture.contemplate_with_env(
+        &["fixture", "author", "--git-init", "--author", "Ada"],
+        &env,
+    );
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        authors("author"),
+        Some(toml::Value::Array(vec![
+            "Ada <configured@example.com>".into()
+        ]))
+    );
+    assert_eq!(committer("author"), "Ada <configured@example.com>");
+
+    let output = fixture.contemplate_with_env(
+        &[
+            "fixture",
+            "both",
+            "--git-init",
+            "--author",
+            "Ada",
+            "--email",
+            "ada@example.com",
+        ],
+        &env,
+    );
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        authors("both"),
+        Some(toml::Value::Array(vec!["Ada <ada@example.com>".into()]))
+    );
+    assert_eq!(committer("both"), "Ada <ada@example.com>");
+}
+
+#[test]
+fn names_the_template_in_the_initial_commit_unless_told_otherwise() {
+    let fixture = Fixture::new();
+    let sha = Repository::open(fixture.root.path().join("template.git"))
+        .unwrap()
+        .refname_to_id("refs/heads/main")
+        .unwrap()
+        .to_string();
+    let message = |name: &str| {
+        let repo = Repository::open(fixture.work().join(name)).unwrap();
+        let commit = repo.head().unwrap().peel_to_commit().unwrap();
+        commit.message().unwrap().to_string()
+    };
+    let output = fixture.contemplate(&["fixture", "default", "--git-init"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        message("default"),
+        format!("Initial commit from fixture@{}", sha)
+    );
+
+    let output = fixture.contemplate(&[
+        "fixture",
+        "custom",
+        "--git-init",
+        "--commit-message",
+        "chore: start\n\nfrom the fixture",
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(message("custom"), "chore: start\n\nfrom the fixture");
+}
+
+#[test]
+fn renders_liquid_files_with_the_liquid_engine() {
+    let fixture = Fixture::with_files(&[
+        (
+            "pkg/README.md.liquid",
+            "# {{ project_name | upcase }}\n{% if docker == \"true\" %}docker{% endif %}\n",
+        ),
+        ("pkg/NOTES.md", "{{project_name}} {% raw %}\n"),
+    ]);
+    let output = fixture.contemplate(&[
+        "fixture",
+        "demo",
+        "--engine",
+        "liquid",
+        "--set",
+        "docker=true",
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+    let demo = fixture.work().join("demo");
+    assert_eq!(
+        entries(&demo),
+        ["Cargo.toml", "NOTES.md", "README.md", "src"]
+    );
+    assert_eq!(
+        fs::read_to_string(demo.join("README.md")).unwrap(),
+        "# DEMO\ndocker\n"
+    );
+    assert_eq!(
+        fs::read_to_string(demo.join("NOTES.md")).unwrap(),
+        "demo {% raw %}\n"
+    );
+
+    let output = fixture.contemplate(&["fixture", "plain"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(fixture.work().join("plain/README.md.liquid").is_file());
+}
+
+#[test]
+fn pins_the_template_commit_with_a_lockfile() {
+    let fixture = Fixture::new();
+    let output = fixture.contemplate(&["fixture", "demo", "--source-ref-lock"]);
+    assert!(output.status.success(), "{:?}", output);
+    let lock = fs::read_to_string(fixture.work().join("demo/contemplate.lock")).unwrap();
+    let head = Repository::open(fixture.root.path().join("template.git"))
+        .unwrap()
+        .head()
+        .unwrap()
+        .target()
+        .unwrap();
+    assert!(lock.contains(&format!("commit = \"{}\"", head)), "{}", lock);
+
+    fs::write(fixture.work().join("contemplate.lock"), &lock).unwrap();
+    let output = fixture.contemplate(&["fixture", "locked", "--locked"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(fixture.work().join("locked/Cargo.toml").is_file());
+
+    let gone = lock.replace(&head.to_string(), &"0".repeat(40));
+    fs::write(fixture.work().join("contemplate.lock"), gone).unwrap();
+    let output = fixture.contemplate(&["fixture", "gone", "--locked"]);
+    assert!(!output.status.success(), "{:?}", output);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("no longer has"));
+}
+
+#[test]
+fn scaffolds_into_a_new_worktree() {
+    let fixture = Fixture::new();
+    let output = fixture.contemplate(&["fixture", "demo", "--worktree"]);
+    assert!(!output.status.success(), "{:?}", output);
+
+    write(&fixture.work(), "README.md", "the work under way\n");
+    commit_all(&fixture.work());
+    let output = fixture.contemplate(&["fixture", "demo", "--worktree"]);
+    assert!(output.status.success(), "{:?}", output);
+    let repo = Repository::open(fixture.work().join("demo")).unwrap();
+    assert!(repo.is_worktree());
+    let head = repo.head().unwrap();
+    assert_eq!(head.shorthand(), Some("demo"));
+    let commit = head.peel_to_commit().unwrap();
+    assert_eq!(commit.parent_count(), 0);
+    assert!(commit
+        .tree()
+        .unwrap()
+        .get_path(Path::new("src/main.rs"))
+        .is_ok());
+    assert!(repo.statuses(None).unwrap().is_empty());
+    assert_eq!(
+        entries(&fixture.work().join("demo")),
+        [".git", "Cargo.toml", "src"]
+    );
+}
+
+#[test]
+fn refuses_to_scaffold_lfs_pointers() {
+    let pointer = "version https://git-lfs.github.com/spec/v1\n\
+                   oid sha256:4d7a214614ab2935c943f9e0ff69d22eadbb8f32b1258daaa5e2ca24d17e2393\n\
+                   size 12345\n";
+    let fixture = Fixture::with_files(&[("pkg/assets/logo.png", pointer)]);
+    let output = fixture.contemplate(&["fixture", "demo"]);
+    assert!(!output.status.success(), "{:?}", output);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("assets/logo.png"));
+    assert_eq!(entries(&fixture.work()), Vec::<String>::new());
+}
+
+#[test]
+fn updates_a_project_with_the_changes_of_its_template() {
+    let fixture = Fixture::with_files(&[("pkg/NOTES.md", "# {{project_name}}\nfirst\n")]);
+    for name in ["clean", "changed"] {
+        let output = fixture.contemplate(&["fixture", name, "--source-ref-lock", "--git-init"]);
+        assert!(output.status.success(), "{:?}", output);
+    }
+    let changed = fixture.work().join("changed");
+    fs::write(changed.join("src/main.rs"), "fn main() { todo!() }\n").unwrap();
+    fixture.change_template(&[
+        ("pkg/NOTES.md", "# {{project_name}}\nsecond\n"),
+        ("pkg/src/main.rs", "fn main() { println!(\"hi\"); }\n"),
+        ("pkg/src/lib.rs", "pub fn lib() {}\n"),
+    ]);
+    let update = |project: &Path| {
+        Command::new(env!("CARGO_BIN_EXE_cargo-contemplate"))
+            .args(["contemplate", "update", "--since-commit"])
+            .arg(project)
+            .env("CONTEMPLATE_HOME", fixture.root.path().join("home"))
+            .output()
+            .unwrap()
+    };
+
+    let clean = fixture.work().join("clean");
+    let lock = fs::read_to_string(clean.join("contemplate.lock")).unwrap();
+    let output = update(&clean);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        fs::read_to_string(clean.join("NOTES.md")).unwrap(),
+        "# clean\nsecond\n"
+    );
+    assert!(fs::read_to_string(clean.join("src/main.rs"))
+        .unwrap()
+        .contains("hi"));
+    assert!(clean.join("src/lib.rs").is_file());
+    assert_ne!(
+        fs::read_to_string(clean.join("contemplate.lock")).unwrap(),
+        lock
+    );
+
+    let lock = fs::read_to_string(changed.join("contemplate.lock")).unwrap();
+    let output = update(&changed);
+    assert!(!output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("src/main.rs does not apply cleanly"),
+        "{}",
+        stdout
+    );
+    assert_eq!(
+        fs::read_to_string(changed.join("NOTES.md")).unwrap(),
+        "# changed\nsecond\n"
+    );
+    assert_eq!(
+        fs::read_to_string(changed.join("src/main.rs")).unwrap(),
+        "fn main() { todo!() }\n"
+    );
+    assert_eq!(
+        fs::read_to_string(changed.join("contemplate.lock")).unwrap(),
+        lock
+    );
+}
+
+#[test]
+fn verifies_a_template_without_scaffolding_it() {
+    let fixture = Fixture::new();
+    let output = fixture.contemplate(&["verify", "fixture"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(entries(&fixture.work()), Vec::<String>::new());
+
+    let fixture = Fixture::with_files(&[
+        ("pkg/Cargo.toml", "[package\n"),
+        (
+            "contemplate.toml",
+            "base = \"no-such-class\"\nminimal = [\"[\"]\n",
+        ),
+    ]);
+    let output = fixture.contemplate(&["verify", "fixture"]);
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Cargo.toml"), "{}", stdout);
+    assert!(!stdout.contains("`base`"), "{}", stdout);
+
+    let output = fixture.contemplate(&["verify", "fixture", "--all-errors"]);
+    assert_eq!(output.status.code(), Some(23));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("`base` is not a class"), "{}", stdout);
+    assert!(stdout.contains("`minimal`"), "{}", stdout);
+}
+
+#[test]
+fn rejects_a_package_outside_of_the_template() {
+    let fixture = Fixture::new();
+    write(
+        &fixture.root.path().join("secret"),
+        "Cargo.toml",
+        "[package]\n",
+    );
+    for package in ["../secret", "pkg/../../secret", "/etc", "../../../../etc"] {
+        let output = fixture.contemplate(&["fixture", "demo", "--package", package]);
+        assert!(!output.status.success(), "{}: {:?}", package, output);
+        assert!(String::from_utf8_lossy(&output.stdout).contains("outside"));
+        assert_eq!(entries(&fixture.work()), Vec::<String>::new());
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn rejects_a_package_symlinked_out_of_the_template() {
+    let fixture = Fixture::with_files(&[("contemplate.toml", "package = \"escape\"\n")]);
+    let secret = fixture.root.path().join("secret");
+    write(&secret, "Cargo.toml", "[package]\n");
+    // a symlink a template can carry, pointing at a directory of the machine
+    let src = fixture.root.path().join("src");
+    std::os::unix::fs::symlink(&secret, src.join("escape")).unwrap();
+    let repo = Repository::open(&src).unwrap();
+    let mut index = repo.index().unwrap();
+    index
+        .add_all(["*"], git2::IndexAddOption::DEFAULT, None)
+        .unwrap();
+    let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+    let signature = Signature::now("fixture", "fixture@example.com").unwrap();
+    let parent = repo.head().unwrap().peel_to_commit().unwrap();
+    repo.commit(
+        Some("HEAD"),
+        &signature,
+        &signature,
+        "escape",
+        &tree,
+        &[&parent],
+    )
+    .unwrap();
+    let bare = Repository::open(fixture.root.path().join("template.git")).unwrap();
+    bare.remote_anonymous(&file_url(&src))
+        .unwrap()
+        .fetch(&["+refs/heads/main:refs/heads/main"], None, None)
+        .unwrap();
+
+    let output = fixture.contemplate(&["fixture", "demo"]);
+    assert!(!output.status.success(), "{:?}", output);
+    assert!(
+        String::from_utf8_lossy(&output.stdout).contains("outside"),
+        "{:?}",
+        output
+    );
+    assert_eq!(entries(&fixture.work()), Vec::<String>::new());
+}
+
+#[test]
+fn keeps_a_history_only_when_asked() {
+    let fixture = Fixture::new();
+    let output = fixture.contemplate(&["fixture", "unrecorded"]);
+    assert!(output.status.success(), "{:?}", output);
+    let output = fixture.contemplate(&["history"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(String::from_utf8_lossy(&output.stdout).starts_with("nothing recorded"));
+    assert!(!fixture.root.path().join("home/history.log").exists());
+
+    let registry = fixture.root.path().join("home/templates.toml");
+    let content = fs::read_to_string(&registry).unwrap();
+    fs::write(&registry, format!("history = true\n{}", content)).unwrap();
+    let output = fixture.contemplate(&["fixture", "recorded"]);
+    assert!(output.status.success(), "{:?}", output);
+    let output = fixture.contemplate(&["history"]);
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let recorded = fs::canonicalize(fixture.work().join("recorded")).unwrap();
+    let lines: Vec<_> = stdout.lines().collect();
+    assert_eq!(lines.len(), 1, "{}", stdout);
+    assert!(
+        lines[0].ends_with(&format!("  fixture  {}", recorded.display())),
+        "{}",
+        stdout
+    );
+    // `YYYY-MM-DD HH:MM` first
+    assert_eq!(lines[0].find("  fixture"), Some(16), "{}", stdout);
+}
+
+#[test]
+fn says_where_a_class_is_defined() {
+    let fixture = Fixture::new();
+    let registry = fixture.root.path().join("home/templates.toml");
+    let which = |class: &str, format: &str| {
+        let output = fixture.contemplate(&["which", class, "--output-format", format]);
+        assert!(output.status.success(), "{:?}", output);
+        String::from_utf8(output.stdout).unwrap()
+    };
+    assert_eq!(
+        which("fixture", "plain"),
+        format!("{}\n", registry.display())
+    );
+    assert_eq!(which("phat-contract", "plain"), "built-in\n");
+    fs::write(fixture.work().join("template.tar.gz"), "").unwrap();
+    assert_eq!(which("template.tar.gz", "plain"), "archive\n");
+
+    let table = which("fixture", "table");
+    let lines: Vec<_> = table.lines().collect();
+    assert!(lines[0].starts_with("NAME     URL"), "{}", table);
+    let cells: Vec<_> = lines[1].split_whitespace().collect();
+    assert_eq!(cells[0], "fixture", "{}", table);
+    assert_eq!(
+        cells[2..],
+        ["main", "pkg", "-", &registry.display().to_string()]
+    );
+    let json: serde_json::Value = serde_json::from_str(&which("fixture", "json")).unwrap();
+    assert_eq!(json["name"], "fixture");
+    assert_eq!(json["source"], registry.display().to_string());
+
+    fixture.register(&format!(
+        "[templates.phat-contract]\nurl = \"{}\"\nbranch = \"main\"\npackage = \"pkg\"\n",
+        file_url(&fixture.root.path().join("template.git"))
+    ));
+    assert_eq!(
+        which("phat-contract", "plain"),
+        format!("{}, replacing the built-in class\n", registry.display())
+    );
+
+    let output = fixture.contemplate(&["which", "nope"]);
+    assert_eq!(output.status.code(), Some(10), "{:?}", output);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("no class is named `nope`"));
+}
+
+#[test]
+fn writes_a_starter_registry_with_init() {
+    let fixture = Fixture::new();
+    let home = fixture.root.path().join("fresh");
+    let env = [("CONTEMPLATE_HOME", home.to_str().unwrap())];
+    let output = fixture.contemplate_with_env(&["init"], &env);
+    assert!(output.status.success(), "{:?}", output);
+    let registry = fs::read_to_string(home.join("templates.toml")).unwrap();
+    assert!(
+        registry.contains("# [templates.phat-contract]\n"),
+        "{}",
+        registry
+    );
+    // all of it commented out, the built-in classes are still the ones used
+    assert!(registry.parse::<toml::Table>().unwrap().is_empty());
+    let output = fixture.contemplate_with_env(&["list", "--output-format", "plain"], &env);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("phat-contract\n"));
+
+    fs::write(home.join("templates.toml"), "# mine\n").unwrap();
+    let output = fixture.contemplate_with_env(&["init"], &env);
+    assert_eq!(output.status.code(), Some(30), "{:?}", output);
+    assert_eq!(
+        fs::read_to_string(home.join("templates.toml")).unwrap(),
+        "# mine\n"
+    );
+    let output = fixture.contemplate_with_env(&["init", "--force"], &env);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        fs::read_to_string(home.join("templates.toml")).unwrap(),
+        registry
+    );
+}
+
+#[test]
+fn lists_the_classes_with_a_tag() {
+    let fixture = Fixture::new();
+    let home = fixture.root.path().join("home");
+    let mut registry = fs::read_to_string(home.join("templates.toml")).unwrap();
+    registry.push_str("tags = [\"Ink\", \"contract\"]\n");
+    registry.push_str("[templates.other]\nurl = \"https://example.com/tpl.git\"\nbranch = \"main\"\ntags = [\"ink\"]\n");
+    fs::write(home.join("templates.toml"), registry).unwrap();
+
+    let list = |args: &[&str]| {
+        let output = fixture.contemplate(&[&["list", "--output-format", "plain"], args].concat());
+        assert!(output.status.success(), "{:?}", output);
+        String::from_utf8(output.stdout).unwrap()
+    };
+    assert_eq!(list(&["--tag", "CONTRACT"]), "fixture\n");
+    assert_eq!(list(&["--tag", "ink"]), "fixture\nother\n");
+    assert_eq!(
+        list(&["--tag", "contract", "--tag", "ink"]),
+        "fixture\nother\n"
+    );
+    let all = ["--tag", "contract", "--tag", "ink", "--tag-match", "all"];
+    assert_eq!(list(&all), "fixture\n");
+    assert_eq!(list(&["--tag", "none"]), "");
+}
+
+#[test]
+fn prints_the_url_that_would_be_fetched() {
+    let fixture = Fixture::new();
+    fixture.register(
+        "[templates.remote]\nurl = \"https://github.com/org/tpl.git\"\nbranch = \"main\"\npackage = \"pkg\"\n",
+    );
+    let output = fixture.contemplate(&[
+        "remote",
+        "--print-url",
+        "--branch",
+        "origin/next",
+        "--mirror",
+        "https://git.internal/github/",
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "url=https://git.internal/github/org/tpl.git\nbranch=next\npackage=pkg\n"
+    );
+}
+
+#[test]
+fn rewrites_the_url_to_the_mirror() {
+    let fixture = Fixture::new();
+    fixture.register(concat!(
+        "[templates.https]\nurl = \"https://github.com/org/tpl.git\"\nbranch = \"main\"\n",
+        "[templates.bare]\nurl = \"https://github.com/org/tpl\"\nbranch = \"main\"\n",
+        "[templates.scp]\nurl = \"git@github.com:org/tpl.git\"\nbranch = \"main\"\n",
+    ));
+    let url = |class: &str, mirror: &str| {
+        let output = fixture.contemplate(&[class, "--print-url", "--mirror", mirror]);
+        let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+        (output.status.code(), stdout)
+    };
+    let mirror = "https://git.internal/github";
+    let (code, stdout) = url("https", mirror);
+    assert_eq!(code, Some(0));
+    assert!(stdout.starts_with("url=https://git.internal/github/org/tpl.git\n"));
+    // the path is kept as it is, `.git` or not
+    let (_, stdout) = url("bare", mirror);
+    assert!(stdout.starts_with("url=https://git.internal/github/org/tpl\n"));
+    let (code, stdout) = url("scp", mirror);
+    assert_eq!(code, Some(0));
+    assert!(stdout.starts_with("url=https://git.internal/github/org/tpl.git\n"));
+
+    let (code, stdout) = url("https", "http://git.internal/github");
+    assert_eq!(code, Some(0));
+    assert!(stdout.contains("WITHOUT TLS"), "{}", stdout);
+    let (code, _) = url("https", "not a url");
+    assert_eq!(code, Some(20));
+
+    // the fixture is a file:// url, which stays as it is
+    let output = fixture.contemplate(&["fixture", "demo", "--mirror", mirror]);
+    assert!(output.status.success(), "{:?}", output);
+}
+
+#[test]
+fn fetches_the_base_templates_from_the_mirror_too() {
+    let fixture = Fixture::with_files(&[("contemplate.toml", "base = \"remote\"\n")]);
+    fixture.register(
+        "[templates.remote]\nurl = \"https://github.com/org/base.git\"\nbranch = \"main\"\n",
+    );
+    // nothing listens there, the base is only looked for on the mirror
+    let output = fixture.contemplate(&["fixture", "demo", "--mirror", "https://127.0.0.1:9"]);
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("https://127.0.0.1:9/org/base.git -> "),
+        "{}",
+        stdout
+    );
+    assert!(!stdout.contains("github.com"), "{}", stdout);
+}
+
+#[test]
+fn reports_an_error_as_json() {
+    let fixture = Fixture::new();
+    let output = fixture.contemplate(&["no-such-template", "demo", "--json"]);
+    let error: serde_json::Value = serde_json::from_slice(&output.stderr).unwrap();
+    assert_eq!(error["error"]["kind"], "NoSuchClass");
+    assert_eq!(
+        output.status.code(),
+        error["error"]["code"].as_i64().map(|code| code as i32)
+    );
+}
+
+#[test]
+fn reports_an_error_of_a_subcommand_as_json() {
+    let fixture = Fixture::new();
+    for args in [
+        ["which", "no-such-template", "--json"],
+        ["verify", "no-such-template", "--json"],
+    ] {
+        let output = fixture.contemplate(&args);
+        let error: serde_json::Value = serde_json::from_slice(&output.stderr).unwrap();
+        assert_eq!(error["error"]["kind"], "NoSuchClass");
+        assert_eq!(output.status.code(), Some(10));
+    }
+}
+
+#[test]
+fn rejects_an_unknown_class() {
+    let fixture = Fixture::new();
+    let output = fixture.contemplate(&["no-such-template", "demo"]);
+    assert!(!output.status.success());
+    assert_eq!(entries(&fixture.work()), Vec::<String>::new());
+}